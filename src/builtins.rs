@@ -0,0 +1,432 @@
+use {
+    crate::interpreter::{
+        call_fn, resolve_index, seed_rng, structurally_equal, BuiltinFn, FnType, Int, RuntimeError,
+        State, Value, ValueType,
+    },
+    std::{collections::HashMap, io::Write},
+};
+
+pub fn make_builtins() -> HashMap<String, Value> {
+    [
+        ("print", ValueType::Nothing),
+        ("read_line", ValueType::String),
+        ("char_at", ValueType::Char),
+        ("typeof", ValueType::String),
+        ("set_global", ValueType::Nothing),
+        ("len", ValueType::Int),
+        ("format", ValueType::String),
+        ("upper", ValueType::String),
+        ("lower", ValueType::String),
+        ("trim", ValueType::String),
+        ("map", ValueType::Array),
+        ("push", ValueType::Array),
+        ("pop", ValueType::Array),
+        ("slice", ValueType::Array),
+        ("filter", ValueType::Array),
+        ("int", ValueType::Int),
+        ("float", ValueType::Float),
+        ("char", ValueType::Char),
+        ("string", ValueType::String),
+        ("floor", ValueType::Int),
+        ("ceil", ValueType::Int),
+        ("round", ValueType::Int),
+        ("assert", ValueType::Nothing),
+        ("assert_eq", ValueType::Nothing),
+        ("error", ValueType::Nothing),
+        ("exit", ValueType::Nothing),
+        ("clock", ValueType::Float),
+        ("rand_seed", ValueType::Nothing),
+        ("rand_int", ValueType::Int),
+        ("sb_new", ValueType::Builder),
+        ("sb_push", ValueType::Nothing),
+        ("sb_build", ValueType::String),
+    ]
+    .into_iter()
+    .map(|(name, return_type)| {
+        let builtin = BuiltinFn {
+            name: name.to_string(),
+            return_type,
+        };
+        (name.to_string(), Value::Fn(FnType::Builtin(builtin)))
+    })
+    .collect()
+}
+
+/// Builtins get the whole interpreter state, so besides their arguments
+/// they can do I/O, call back into functions and change globals.
+pub fn call_builtin(
+    name: &str,
+    passed_args: Vec<Value>,
+    return_type: ValueType,
+    state: &mut State,
+) -> Result<Value, RuntimeError> {
+    let value = match name {
+        "print" => {
+            let strings: Vec<_> = passed_args.iter().map(|arg| arg.to_string()).collect();
+            writeln!(state.io.output, "{}", strings.join(" "))?;
+            Value::Nothing
+        }
+        "read_line" => {
+            expect_args(&passed_args, 0)?;
+            let mut line = String::new();
+            state.io.input.read_line(&mut line)?;
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Value::String(line)
+        }
+        "clock" => {
+            expect_args(&passed_args, 0)?;
+            Value::Float((state.clock)().as_secs_f64())
+        }
+        "rand_seed" => match passed_args.as_slice() {
+            [Value::Int(seed)] => {
+                state.rng = seed_rng(*seed as u64);
+                Value::Nothing
+            }
+            [other] => return Err(type_mismatch(ValueType::Int, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        // Both bounds are included
+        "rand_int" => match passed_args.as_slice() {
+            [Value::Int(lo), Value::Int(hi)] if lo > hi => {
+                return Err(RuntimeError::EmptyRange(*lo, *hi))
+            }
+            [Value::Int(lo), Value::Int(hi)] => {
+                let span = (i128::from(*hi) - i128::from(*lo) + 1) as u128;
+                let offset = u128::from(xorshift(&mut state.rng)) % span;
+                Value::Int(Int::try_from(i128::from(*lo) + offset as i128).unwrap())
+            }
+            [Value::Int(_), other] | [other, _] => {
+                return Err(type_mismatch(ValueType::Int, other))
+            }
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        "typeof" => {
+            expect_args(&passed_args, 1)?;
+            // Names match `get_valuetype_from`, so they can be fed back in
+            Value::String(passed_args[0].get_type().to_string())
+        }
+        "len" => {
+            let len = match passed_args.as_slice() {
+                [Value::String(s)] => s.chars().count(),
+                [Value::Array(values)] => values.len(),
+                [Value::Map(entries)] => entries.len(),
+                [other] => return Err(type_mismatch(ValueType::Array, other)),
+                _ => return Err(wrong_arg_count(&passed_args, 1)),
+            };
+            Value::Int(Int::try_from(len).map_err(|_| RuntimeError::IntegerOverflow)?)
+        }
+        "format" => match passed_args.split_first() {
+            Some((Value::String(template), args)) => Value::String(format(template, args)?),
+            Some((other, _)) => return Err(type_mismatch(ValueType::String, other)),
+            None => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        // Unicode aware, like `str::to_uppercase`: "straße" becomes "STRASSE"
+        "upper" | "lower" | "trim" => match passed_args.as_slice() {
+            [Value::String(s)] => Value::String(match name {
+                "upper" => s.to_uppercase(),
+                "lower" => s.to_lowercase(),
+                _ => s.trim().to_string(),
+            }),
+            [other] => return Err(type_mismatch(ValueType::String, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "map" => match passed_args.as_slice() {
+            [Value::Array(values), Value::Fn(func)] => {
+                let mut mapped = Vec::new();
+                for value in values {
+                    mapped.push(call_fn(func, vec![value.clone()], state)?);
+                }
+                Value::Array(mapped)
+            }
+            [Value::Array(_), other] => return Err(type_mismatch(ValueType::Fn, other)),
+            [other, _] => return Err(type_mismatch(ValueType::Array, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        // Arrays are values, so these return a new array rather than
+        // changing the one passed in: `arr := push(arr, 4);`. The array is
+        // moved out of the arguments, not copied
+        "push" => {
+            expect_args(&passed_args, 2)?;
+            let mut args = passed_args.into_iter();
+            match (args.next().unwrap(), args.next().unwrap()) {
+                (Value::Array(mut values), value) => {
+                    values.push(value);
+                    Value::Array(values)
+                }
+                (other, _) => return Err(type_mismatch(ValueType::Array, &other)),
+            }
+        }
+        // Drops the last element, which `arr[-1]` reads beforehand
+        "pop" => {
+            expect_args(&passed_args, 1)?;
+            match passed_args.into_iter().next().unwrap() {
+                Value::Array(mut values) => {
+                    resolve_index(-1, values.len())?;
+                    values.pop();
+                    Value::Array(values)
+                }
+                other => return Err(type_mismatch(ValueType::Array, &other)),
+            }
+        }
+        // `slice(arr, start, end)` takes `start` up to but not including
+        // `end`. Negative bounds count from the end like indices do, bounds
+        // past either end are clamped, so it never fails on a valid array
+        "slice" => match passed_args.as_slice() {
+            [Value::Array(values), Value::Int(start), Value::Int(end)] => {
+                let start = clamp_bound(*start, values.len());
+                let end = clamp_bound(*end, values.len()).max(start);
+                Value::Array(values[start..end].to_vec())
+            }
+            [Value::Array(_), Value::Int(_), other] | [Value::Array(_), other, _] => {
+                return Err(type_mismatch(ValueType::Int, other))
+            }
+            [other, _, _] => return Err(type_mismatch(ValueType::Array, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 3)),
+        },
+        "filter" => match passed_args.as_slice() {
+            [Value::Array(values), Value::Fn(func)] => {
+                let mut kept = Vec::new();
+                for value in values {
+                    match call_fn(func, vec![value.clone()], state)? {
+                        Value::Bool(true) => kept.push(value.clone()),
+                        Value::Bool(false) => {}
+                        other => return Err(type_mismatch(ValueType::Bool, &other)),
+                    }
+                }
+                Value::Array(kept)
+            }
+            [Value::Array(_), other] => return Err(type_mismatch(ValueType::Fn, other)),
+            [other, _] => return Err(type_mismatch(ValueType::Array, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        // Chars convert to and from their Unicode code point
+        "int" => match passed_args.as_slice() {
+            [Value::Int(n)] => Value::Int(*n),
+            [Value::Char(c)] => Value::Int(*c as Int),
+            // Drops the fraction, like a cast would
+            [Value::Float(n)] => Value::Int(float_to_int(n.trunc())?),
+            [other] => return Err(type_mismatch(ValueType::Float, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "char" => match passed_args.as_slice() {
+            [Value::Char(c)] => Value::Char(*c),
+            [Value::Int(n)] => Value::Char(
+                u32::try_from(*n)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| RuntimeError::NotAChar(n.to_string()))?,
+            ),
+            [Value::String(s)] => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Value::Char(c),
+                    _ => return Err(RuntimeError::NotAChar(format!("{:?}", s))),
+                }
+            }
+            [other] => return Err(type_mismatch(ValueType::Int, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "string" => {
+            expect_args(&passed_args, 1)?;
+            Value::String(passed_args[0].to_string())
+        }
+        "float" => match passed_args.as_slice() {
+            [Value::Int(n)] => Value::Float(*n as f64),
+            [Value::Float(n)] => Value::Float(*n),
+            [other] => return Err(type_mismatch(ValueType::Float, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        // Halfway cases round to the nearest even number, like `f64::round_ties_even`
+        "floor" | "ceil" | "round" => match passed_args.as_slice() {
+            [Value::Int(n)] => Value::Int(*n),
+            [Value::Float(n)] => {
+                let rounded = match name {
+                    "floor" => n.floor(),
+                    "ceil" => n.ceil(),
+                    _ => n.round_ties_even(),
+                };
+                Value::Int(float_to_int(rounded)?)
+            }
+            [other] => return Err(type_mismatch(ValueType::Float, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "assert" => match passed_args.as_slice() {
+            [Value::Bool(true)] => Value::Nothing,
+            [Value::Bool(false)] => return Err(RuntimeError::AssertionFailed(None)),
+            [other] => return Err(type_mismatch(ValueType::Bool, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "assert_eq" => match passed_args.as_slice() {
+            // Equal like `==` has it, so `assert_eq(1, 1.0)` holds
+            [left, right] if structurally_equal(left, right) => Value::Nothing,
+            [left, right] => {
+                let sides = (quoted(left), quoted(right));
+                return Err(RuntimeError::AssertionFailed(Some(sides)));
+            }
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        "error" => match passed_args.as_slice() {
+            [Value::String(message)] => return Err(RuntimeError::UserError(message.clone())),
+            [other] => return Err(type_mismatch(ValueType::String, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "exit" => match passed_args.as_slice() {
+            [Value::Int(code)] => return Err(RuntimeError::Exit(*code)),
+            [other] => return Err(type_mismatch(ValueType::Int, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        "set_global" => match passed_args.as_slice() {
+            [Value::String(global), value] => {
+                state.toplevel_scope.set_global(global, value.clone())?;
+                Value::Nothing
+            }
+            [other, _] => return Err(type_mismatch(ValueType::String, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        "char_at" => match passed_args.as_slice() {
+            [Value::String(s), Value::Int(index)] => {
+                let i = resolve_index(*index, s.chars().count())?;
+                Value::Char(s.chars().nth(i).unwrap())
+            }
+            [Value::String(_), other] => return Err(type_mismatch(ValueType::Int, other)),
+            [other, _] => return Err(type_mismatch(ValueType::String, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        // `+` copies the whole string every time, a builder appends in
+        // place so building a string piece by piece stays linear
+        "sb_new" => {
+            expect_args(&passed_args, 0)?;
+            state.builders.push(String::new());
+            Value::Builder(state.builders.len() - 1)
+        }
+        "sb_push" => match passed_args.as_slice() {
+            [Value::Builder(id), Value::String(s)] => {
+                builder(&mut state.builders, *id)?.push_str(s);
+                Value::Nothing
+            }
+            [Value::Builder(id), Value::Char(c)] => {
+                builder(&mut state.builders, *id)?.push(*c);
+                Value::Nothing
+            }
+            [Value::Builder(_), other] => return Err(type_mismatch(ValueType::String, other)),
+            [other, _] => return Err(type_mismatch(ValueType::Builder, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 2)),
+        },
+        "sb_build" => match passed_args.as_slice() {
+            [Value::Builder(id)] => Value::String(builder(&mut state.builders, *id)?.clone()),
+            [other] => return Err(type_mismatch(ValueType::Builder, other)),
+            _ => return Err(wrong_arg_count(&passed_args, 1)),
+        },
+        _ => return Err(RuntimeError::UndefinedVariable(name.to_string())),
+    };
+    debug_assert_eq!(value.get_type(), return_type);
+    Ok(value)
+}
+
+/// The buffer behind a `Value::Builder`, which may have been made up by a
+/// host or come from another interpreter.
+fn builder(builders: &mut [String], id: usize) -> Result<&mut String, RuntimeError> {
+    builders.get_mut(id).ok_or(RuntimeError::UnknownBuilder(id))
+}
+
+/// Shows strings and chars in quotes, so `assert_eq(1, "1")` doesn't
+/// report `1 != 1`.
+fn quoted(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        Value::Char(c) => format!("{:?}", c),
+        other => other.to_string(),
+    }
+}
+
+/// `rounded` must already be a whole number; NaN, infinities and values
+/// outside the `Int` range don't fit.
+fn float_to_int(rounded: f64) -> Result<Int, RuntimeError> {
+    if rounded.is_finite() && rounded >= Int::MIN as f64 && rounded < Int::MAX as f64 {
+        Ok(rounded as Int)
+    } else {
+        Err(RuntimeError::IntegerOverflow)
+    }
+}
+
+/// Replaces each `{}` in `template` with the next argument, `{{` and `}}`
+/// stand for literal braces. Needs exactly one argument per placeholder.
+/// A quoted template has already been through interpolation, which turns
+/// `{{` into `{`, so templates with escapes are written as raw strings.
+fn format(template: &str, args: &[Value]) -> Result<String, RuntimeError> {
+    let mut out = String::new();
+    let mut placeholders = 0;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                if let Some(arg) = args.get(placeholders) {
+                    out.push_str(&arg.to_string());
+                }
+                placeholders += 1;
+            }
+            ('{' | '}', _) => {
+                return Err(RuntimeError::InvalidFormat(format!(
+                    "unmatched '{}', use '{}{}' for a literal one",
+                    c, c, c
+                )))
+            }
+            _ => out.push(c),
+        }
+    }
+    if placeholders != args.len() {
+        return Err(RuntimeError::FormatArgCount {
+            placeholders,
+            args: args.len(),
+        });
+    }
+    Ok(out)
+}
+
+fn xorshift(rng: &mut u64) -> u64 {
+    *rng ^= *rng << 13;
+    *rng ^= *rng >> 7;
+    *rng ^= *rng << 17;
+    *rng
+}
+
+fn clamp_bound(bound: Int, len: usize) -> usize {
+    let bound = if bound < 0 {
+        len.saturating_sub(bound.unsigned_abs() as usize)
+    } else {
+        usize::try_from(bound).unwrap_or(usize::MAX)
+    };
+    bound.min(len)
+}
+
+fn expect_args(passed_args: &[Value], expected: usize) -> Result<(), RuntimeError> {
+    if passed_args.len() == expected {
+        Ok(())
+    } else {
+        Err(wrong_arg_count(passed_args, expected))
+    }
+}
+
+fn wrong_arg_count(passed_args: &[Value], expected: usize) -> RuntimeError {
+    RuntimeError::WrongArgCount {
+        expected,
+        found: passed_args.len(),
+    }
+}
+
+fn type_mismatch(expected: ValueType, found: &Value) -> RuntimeError {
+    RuntimeError::TypeMismatch {
+        expected,
+        found: found.get_type(),
+    }
+}
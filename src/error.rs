@@ -0,0 +1,119 @@
+use {
+    crate::{lexer::LexError, parser::ParseError},
+    std::ops::Range,
+};
+
+/// An error, pointing at a byte range of the source it came from when that
+/// is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    pub span: Option<Range<usize>>,
+}
+
+impl From<LexError> for Error {
+    fn from(err: LexError) -> Self {
+        Error {
+            message: err.to_string(),
+            span: Some(err.span()),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error {
+            message: err.message,
+            span: err.span,
+        }
+    }
+}
+
+/// Renders `err` like rustc does: the message, the offending source line
+/// and a caret underneath the span.
+pub fn render_error(source: &str, err: &Error) -> String {
+    let Some(span) = &err.span else {
+        return format!("error: {}\n", err.message);
+    };
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line = &source[line_start..line_end];
+    let line_number = source[..start].matches('\n').count() + 1;
+    let column = source[line_start..start].chars().count();
+    let width = source[start..span.end.clamp(start, line_end)]
+        .chars()
+        .count()
+        .max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    format!(
+        "error: {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}\n",
+        err.message,
+        gutter,
+        line_number,
+        column + 1,
+        gutter,
+        line_number,
+        line,
+        gutter,
+        " ".repeat(column),
+        "^".repeat(width)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::program::Program};
+
+    fn render(source: &str) -> String {
+        render_error(source, &Program::compile(source).unwrap_err())
+    }
+
+    #[test]
+    fn points_at_a_lex_error() {
+        assert_eq!(
+            render("x := 1;\ny := \"\\q\";"),
+            "error: invalid escape '\\q'\n --> line 2, column 7\n  |\n2 | y := \"\\q\";\n  |       ^^\n"
+        );
+    }
+
+    #[test]
+    fn points_at_the_token_a_parse_error_is_at() {
+        assert_eq!(
+            render("x := 1;\ny := (1, 2;"),
+            "error: Expected comma, or ')'\n --> line 2, column 11\n  |\n2 | y := (1, 2;\n  |           ^\n"
+        );
+        assert_eq!(
+            render("x := 1 y := 2;"),
+            "error: Expected semicolon\n --> line 1, column 8\n  |\n1 | x := 1 y := 2;\n  |        ^\n"
+        );
+    }
+
+    #[test]
+    fn points_past_the_end_when_input_runs_out() {
+        assert_eq!(
+            render("func f() {"),
+            "error: Expected '}'\n --> line 1, column 11\n  |\n1 | func f() {\n  |           ^\n"
+        );
+    }
+
+    #[test]
+    fn points_at_an_undefined_name() {
+        assert_eq!(
+            render("x := 1;\nprint(x + y);"),
+            "error: undefined variable 'y' at the top level\n --> line 2, column 11\n  |\n2 | print(x + y);\n  |           ^\n"
+        );
+    }
+
+    #[test]
+    fn renders_errors_without_a_span_on_one_line() {
+        let err = Error {
+            message: "Division by zero".to_string(),
+            span: None,
+        };
+        assert_eq!(render_error("", &err), "error: Division by zero\n");
+    }
+}
@@ -1,6 +1,19 @@
-use crate::parser::Operator;
+use crate::{
+    builtins::{call_builtin, make_builtins},
+    lexer::Token,
+    parser::{Mode, Operator, Param},
+};
 
-use {crate::parser::Expr, std::collections::HashMap};
+use {
+    crate::parser::Expr,
+    std::{
+        cmp::Ordering,
+        collections::{BTreeMap, HashMap},
+        fmt::Display,
+        io::{self, BufRead, Write},
+        time::{Duration, Instant, SystemTime},
+    },
+};
 
 pub struct Interpreter {
     pub state: State,
@@ -8,32 +21,1674 @@ pub struct Interpreter {
 }
 
 pub struct State {
-    pub globals: HashMap<String, Expr>,
+    pub toplevel_scope: Scope,
+    pub io: Io,
+    /// How many more expressions may be evaluated, `None` for no limit.
+    pub steps_left: Option<u64>,
+    /// The buffers behind `Value::Builder` handles. Nothing tracks when a
+    /// handle is dropped, so a buffer lives as long as the interpreter.
+    pub builders: Vec<String>,
+    /// How long the interpreter has been running, as read by `clock`.
+    pub clock: Box<dyn FnMut() -> Duration>,
+    pub mode: Mode,
+    /// The xorshift state behind `rand_int`, never zero.
+    pub rng: u64,
+    /// The code passed to `exit`, once the program has called it.
+    pub exit_code: Option<Int>,
+    pub trace: Option<Trace>,
+}
+
+/// Records every evaluated expression, see `Interpreter::with_trace`.
+pub struct Trace {
+    output: Box<dyn Write>,
+    /// How many times each kind of expression was evaluated.
+    pub counts: HashMap<&'static str, u64>,
+}
+
+impl Trace {
+    fn record(&mut self, expr: &Expr, result: &Result<Value, Flow>) -> io::Result<()> {
+        let kind = expr.kind();
+        *self.counts.entry(kind).or_default() += 1;
+        match result {
+            Ok(value) => writeln!(self.output, "{} => {}", kind, value),
+            Err(Flow::Return(value)) => writeln!(self.output, "{} => return {}", kind, value),
+            Err(Flow::Break) => writeln!(self.output, "{} => break", kind),
+            Err(Flow::Continue) => writeln!(self.output, "{} => continue", kind),
+            Err(Flow::Error(err)) => writeln!(self.output, "{} => error: {}", kind, err),
+        }
+    }
+}
+
+/// Where `print` writes to and `read_line` reads from, so embedders and
+/// tests can swap out stdin/stdout.
+pub struct Io {
+    pub input: Box<dyn BufRead>,
+    pub output: Box<dyn Write>,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self {
+            input: Box::new(io::BufReader::new(io::stdin())),
+            output: Box::new(io::stdout()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub value: Value,
+    pub mutable: bool,
+    /// Set for bindings declared with a type, which later assignments have
+    /// to keep
+    pub value_type: Option<ValueType>,
+}
+
+/// Variables visible to the code being evaluated. The first frame holds the
+/// globals, every function call pushes a frame for its locals which only
+/// sees itself and the globals. Blocks push frames on top of that which
+/// see everything the code around them does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    frames: Vec<HashMap<String, Binding>>,
+    /// Index of the running function's frame, 0 at the top level.
+    function_start: usize,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self {
+            frames: vec![HashMap::new()],
+            function_start: 0,
+        }
+    }
+}
+
+impl Scope {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.frames[self.function_start..]
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(name))
+            .or_else(|| self.frames[0].get(name))
+            .map(|binding| &binding.value)
+    }
+
+    /// Bindings in the outermost (global) frame.
+    pub fn globals(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.frames[0]
+            .iter()
+            .map(|(name, binding)| (name, &binding.value))
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        self.bind(name, value, true, None)
+    }
+
+    pub fn set_const(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        self.bind(name, value, false, None)
+    }
+
+    pub fn set_typed(
+        &mut self,
+        name: &str,
+        value: Value,
+        value_type: ValueType,
+    ) -> Result<(), RuntimeError> {
+        self.bind(name, value, true, Some(value_type))
+    }
+
+    fn bind(
+        &mut self,
+        name: &str,
+        value: Value,
+        mutable: bool,
+        value_type: Option<ValueType>,
+    ) -> Result<(), RuntimeError> {
+        // Assigning to a name the current function (or block) can see
+        // updates it, anything else is a new binding in the innermost frame
+        let frame = match self.frames[self.function_start..]
+            .iter()
+            .rposition(|frame| frame.contains_key(name))
+        {
+            Some(i) => self.function_start + i,
+            None => self.frames.len() - 1,
+        };
+        self.bind_in(frame, name, value, mutable, value_type)
+    }
+
+    /// Binds `name` in the innermost frame, shadowing any outer binding.
+    /// A binding of the same name already in that frame is replaced, along
+    /// with its type, unless it's a constant.
+    pub fn declare(
+        &mut self,
+        name: &str,
+        value: Value,
+        value_type: Option<ValueType>,
+    ) -> Result<(), RuntimeError> {
+        let frame = self.frames.len() - 1;
+        if let Some(Binding { mutable: false, .. }) = self.frames[frame].get(name) {
+            return Err(RuntimeError::AssignToConst(name.to_string()));
+        }
+        self.frames[frame].remove(name);
+        self.bind_in(frame, name, value, true, value_type)
+    }
+
+    /// Assigns to a global, whatever code is running.
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        self.bind_in(0, name, value, true, None)
+    }
+
+    fn bind_in(
+        &mut self,
+        frame: usize,
+        name: &str,
+        value: Value,
+        mutable: bool,
+        value_type: Option<ValueType>,
+    ) -> Result<(), RuntimeError> {
+        let frame = &mut self.frames[frame];
+        let value_type = match frame.get(name) {
+            Some(Binding { mutable: false, .. }) => {
+                return Err(RuntimeError::AssignToConst(name.to_string()))
+            }
+            Some(binding) => value_type.or(binding.value_type),
+            None => value_type,
+        };
+        if let Some(value_type) = value_type {
+            check_type(value_type, &value)?;
+        }
+        frame.insert(
+            name.to_string(),
+            Binding {
+                value,
+                mutable,
+                value_type,
+            },
+        );
+        Ok(())
+    }
+
+    /// Starts a function call's frame, returning what `exit_function` needs
+    /// to restore the caller's.
+    fn enter_function(&mut self) -> usize {
+        self.frames.push(HashMap::new());
+        std::mem::replace(&mut self.function_start, self.frames.len() - 1)
+    }
+
+    fn exit_function(&mut self, caller_start: usize) {
+        self.frames.pop();
+        self.function_start = caller_start;
+    }
+
+    fn enter_block(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    fn exit_block(&mut self) {
+        self.frames.pop();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ValueType {
+    Int,
+    Float,
+    String,
+    Bool,
+    Char,
+    Fn,
+    Tuple,
+    Array,
+    Map,
+    Builder,
+    Nothing,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::Int => write!(f, "int"),
+            ValueType::Float => write!(f, "float"),
+            ValueType::String => write!(f, "string"),
+            ValueType::Bool => write!(f, "bool"),
+            ValueType::Char => write!(f, "char"),
+            ValueType::Fn => write!(f, "fn"),
+            ValueType::Tuple => write!(f, "tuple"),
+            ValueType::Array => write!(f, "array"),
+            ValueType::Map => write!(f, "map"),
+            ValueType::Builder => write!(f, "builder"),
+            ValueType::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+pub fn get_valuetype_from(name: &str) -> Option<ValueType> {
+    match name {
+        "int" => Some(ValueType::Int),
+        "float" => Some(ValueType::Float),
+        "string" => Some(ValueType::String),
+        "bool" => Some(ValueType::Bool),
+        "char" => Some(ValueType::Char),
+        "fn" => Some(ValueType::Fn),
+        "tuple" => Some(ValueType::Tuple),
+        "array" => Some(ValueType::Array),
+        "map" => Some(ValueType::Map),
+        "builder" => Some(ValueType::Builder),
+        "nothing" => Some(ValueType::Nothing),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFn {
+    pub name: String,
+    pub args: Vec<Param>,
+    pub return_type: ValueType,
+    pub body: Vec<Expr>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltinFn {
+    pub name: String,
+    pub return_type: ValueType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FnType {
+    User(UserFn),
+    Builtin(BuiltinFn),
+    /// User functions sharing a name, told apart by their number of
+    /// parameters. Sorted by that number.
+    Overloaded(Vec<UserFn>),
+}
+
+impl Display for UserFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<_> = self.args.iter().map(|arg| arg.to_string()).collect();
+        write!(
+            f,
+            "func {}({}) -> {}",
+            self.name,
+            args.join(", "),
+            self.return_type
+        )
+    }
+}
+
+impl Display for BuiltinFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "builtin {}", self.name)
+    }
+}
+
+impl Display for FnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FnType::User(func) => write!(f, "{}", func),
+            FnType::Builtin(builtin) => write!(f, "{}", builtin),
+            FnType::Overloaded(overloads) => {
+                let overloads: Vec<_> = overloads.iter().map(|func| func.to_string()).collect();
+                write!(f, "{}", overloads.join(", "))
+            }
+        }
+    }
+}
+
+/// The integer type behind `Value::Int`, 64 bits unless the `int32`
+/// feature is enabled. Arithmetic overflowing it is an `IntegerOverflow`
+/// error either way.
+#[cfg(not(feature = "int32"))]
+pub type Int = i64;
+#[cfg(feature = "int32")]
+pub type Int = i32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(Int),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Char(char),
+    Fn(FnType),
+    Tuple(Vec<Value>),
+    Array(Vec<Value>),
+    /// Kept sorted by key, so printing and comparing maps doesn't depend on
+    /// the order entries were added in.
+    Map(BTreeMap<String, Value>),
+    /// A string builder from `sb_new`, indexing into `State::builders` so
+    /// copies of the value all append to the same buffer.
+    Builder(usize),
+    Nothing,
+}
+
+impl Value {
+    pub fn get_type(&self) -> ValueType {
+        match self {
+            Value::Int(_) => ValueType::Int,
+            Value::Float(_) => ValueType::Float,
+            Value::String(_) => ValueType::String,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Char(_) => ValueType::Char,
+            Value::Fn(_) => ValueType::Fn,
+            Value::Tuple(_) => ValueType::Tuple,
+            Value::Array(_) => ValueType::Array,
+            Value::Map(_) => ValueType::Map,
+            Value::Builder(_) => ValueType::Builder,
+            Value::Nothing => ValueType::Nothing,
+        }
+    }
+}
+
+/// Containers nested deeper than this are printed as `...`. Values are plain
+/// trees so they can't be cyclic, but a deep enough one would still flood the
+/// output (and the stack) when printed.
+const MAX_DISPLAY_DEPTH: usize = 32;
+
+/// Displays a value that sits `depth` containers deep.
+struct Nested<'a>(&'a Value, usize);
+
+impl Display for Nested<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Nested(value, depth) = *self;
+        match value {
+            Value::Tuple(_) | Value::Array(_) | Value::Map(_) if depth >= MAX_DISPLAY_DEPTH => {
+                write!(f, "...")
+            }
+            Value::Tuple(values) => {
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| Nested(value, depth + 1).to_string())
+                    .collect();
+                write!(f, "({})", values.join(", "))
+            }
+            Value::Array(values) => {
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| Nested(value, depth + 1).to_string())
+                    .collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Value::Map(entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{:?}: {}", key, Nested(value, depth + 1)))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Value::Int(n) => write!(f, "{}", n),
+            // Debug keeps the fractional part, `1.0` rather than `1`
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Fn(func) => write!(f, "{}", func),
+            Value::Builder(id) => write!(f, "builder #{}", id),
+            Value::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Nested(self, 0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    UndefinedVariable(String),
+    AssignToConst(String),
+    NotCallable(String),
+    UnknownArgument(String),
+    DuplicateArgument(String),
+    MissingArgument(String),
+    WrongArgCount {
+        expected: usize,
+        found: usize,
+    },
+    NoOverload {
+        name: String,
+        found: usize,
+        arities: Vec<usize>,
+    },
+    TypeMismatch {
+        expected: ValueType,
+        found: ValueType,
+    },
+    IndexOutOfBounds {
+        index: Int,
+        len: usize,
+    },
+    DestructureMismatch {
+        expected: usize,
+        found: usize,
+        /// Whether the pattern ends in a `rest...`, so `expected` is a
+        /// minimum.
+        rest: bool,
+    },
+    InvalidOperands {
+        op: Operator,
+        lhs: ValueType,
+        rhs: ValueType,
+    },
+    DivisionByZero,
+    IntegerOverflow,
+    NegativeExponent,
+    InvalidShift(Int),
+    MissingKey(String),
+    StepLimitExceeded,
+    /// Not a failure: raised by `exit` to unwind to `run_program`, which
+    /// records the code and ends the program normally.
+    Exit(Int),
+    EmptyRange(Int, Int),
+    /// Holds both sides, rendered, when raised by `assert_eq`.
+    AssertionFailed(Option<(String, String)>),
+    FormatArgCount {
+        placeholders: usize,
+        args: usize,
+    },
+    InvalidFormat(String),
+    /// An int that isn't a Unicode code point, or a string that isn't
+    /// exactly one character, rendered.
+    NotAChar(String),
+    /// Raised by the `error` builtin, with the script's own message.
+    UserError(String),
+    /// A `Value::Builder` that doesn't belong to this interpreter.
+    UnknownBuilder(usize),
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    ReturnOutsideFunction,
+    Io(String),
+    InvalidExpr(String),
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(err: io::Error) -> Self {
+        RuntimeError::Io(err.to_string())
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            RuntimeError::AssignToConst(name) => {
+                write!(f, "Cannot assign to constant: {}", name)
+            }
+            RuntimeError::NotCallable(name) => write!(f, "{} is not a function", name),
+            RuntimeError::UnknownArgument(name) => write!(f, "Unknown argument: {}", name),
+            RuntimeError::DuplicateArgument(name) => {
+                write!(f, "Argument passed more than once: {}", name)
+            }
+            RuntimeError::MissingArgument(name) => write!(f, "Missing argument: {}", name),
+            RuntimeError::WrongArgCount { expected, found } => {
+                write!(f, "Expected {} arguments, found {}", expected, found)
+            }
+            RuntimeError::NoOverload {
+                name,
+                found,
+                arities,
+            } => {
+                let arities: Vec<_> = arities.iter().map(|arity| arity.to_string()).collect();
+                write!(
+                    f,
+                    "No overload of {} takes {} arguments, it takes {}",
+                    name,
+                    found,
+                    arities.join(" or ")
+                )
+            }
+            RuntimeError::TypeMismatch { expected, found } => {
+                write!(f, "Expected a value of type {}, found {}", expected, found)
+            }
+            RuntimeError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} is out of bounds for length {}", index, len)
+            }
+            RuntimeError::DestructureMismatch {
+                expected,
+                found,
+                rest: false,
+            } => write!(
+                f,
+                "Cannot destructure {} values into {} names",
+                found, expected
+            ),
+            RuntimeError::DestructureMismatch {
+                expected,
+                found,
+                rest: true,
+            } => write!(
+                f,
+                "Cannot destructure {} values into {} names and a rest",
+                found, expected
+            ),
+            RuntimeError::InvalidOperands { op, lhs, rhs } => {
+                write!(f, "Invalid operands for {}: {} and {}", op, lhs, rhs)
+            }
+            RuntimeError::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeError::IntegerOverflow => write!(f, "Integer overflow"),
+            RuntimeError::NegativeExponent => {
+                write!(f, "Cannot raise an integer to a negative power")
+            }
+            RuntimeError::InvalidShift(shift) => {
+                write!(
+                    f,
+                    "Shift amount {} is outside the range 0 to {}",
+                    shift,
+                    Int::BITS - 1
+                )
+            }
+            RuntimeError::MissingKey(key) => write!(f, "Key not found in map: {:?}", key),
+            RuntimeError::StepLimitExceeded => write!(f, "Step limit exceeded"),
+            RuntimeError::Exit(code) => write!(f, "Exited with code {}", code),
+            RuntimeError::EmptyRange(lo, hi) => {
+                write!(f, "Cannot pick a number between {} and {}", lo, hi)
+            }
+            RuntimeError::AssertionFailed(None) => write!(f, "Assertion failed"),
+            RuntimeError::AssertionFailed(Some((left, right))) => {
+                write!(f, "Assertion failed: {} != {}", left, right)
+            }
+            RuntimeError::NotAChar(value) => write!(f, "Cannot convert {} to a char", value),
+            RuntimeError::UserError(message) => write!(f, "{}", message),
+            RuntimeError::UnknownBuilder(id) => write!(f, "Unknown string builder: {}", id),
+            RuntimeError::FormatArgCount { placeholders, args } => write!(
+                f,
+                "Format string has {} placeholders but {} arguments were passed",
+                placeholders, args
+            ),
+            RuntimeError::InvalidFormat(reason) => write!(f, "Invalid format string: {}", reason),
+            RuntimeError::BreakOutsideLoop => write!(f, "break used outside of a loop"),
+            RuntimeError::ContinueOutsideLoop => write!(f, "continue used outside of a loop"),
+            RuntimeError::ReturnOutsideFunction => write!(f, "return used outside of a function"),
+            RuntimeError::Io(err) => write!(f, "I/O error: {}", err),
+            RuntimeError::InvalidExpr(expr) => write!(f, "Cannot evaluate expression: {}", expr),
+        }
+    }
+}
+
+/// Why evaluation of an expression stopped early: loops catch `Break` and
+/// `Continue`, function calls catch `Return`, anything that escapes to the
+/// top level becomes an error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Return(Value),
+    Break,
+    Continue,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Flow {
+    fn from(err: RuntimeError) -> Self {
+        Flow::Error(err)
+    }
+}
+
+impl From<Flow> for RuntimeError {
+    fn from(flow: Flow) -> Self {
+        match flow {
+            Flow::Return(_) => RuntimeError::ReturnOutsideFunction,
+            Flow::Break => RuntimeError::BreakOutsideLoop,
+            Flow::Continue => RuntimeError::ContinueOutsideLoop,
+            Flow::Error(err) => err,
+        }
+    }
 }
 
 impl Interpreter {
     pub fn new(exprs: Vec<Expr>) -> Self {
+        let mut toplevel_scope = Scope::default();
+        for (name, builtin) in make_builtins() {
+            toplevel_scope.set(&name, builtin).unwrap();
+        }
         Self {
             state: State {
-                globals: HashMap::new(),
+                toplevel_scope,
+                io: Io::default(),
+                steps_left: None,
+                builders: Vec::new(),
+                clock: {
+                    let start = Instant::now();
+                    Box::new(move || start.elapsed())
+                },
+                mode: Mode::default(),
+                rng: seed_rng(
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map_or(0, |time| time.as_nanos() as u64),
+                ),
+                exit_code: None,
+                trace: None,
             },
             exprs,
         }
     }
 
-    pub fn run(&mut self) {
-        for expr in &self.exprs {
-            match expr {
-                Expr::BinaryExpr {
-                    op: Operator::SetVal,
-                    lhs,
-                    rhs,
-                } => {
-                    self.state.globals.insert(lhs.to_string(), *rhs.clone());
+    pub fn with_input(mut self, input: Box<dyn BufRead>) -> Self {
+        self.state.io.input = input;
+        self
+    }
+
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.state.io.output = output;
+        self
+    }
+
+    /// Replaces the time source behind `clock`, e.g. with a fake one so
+    /// tests don't depend on how fast they run.
+    pub fn with_clock(mut self, clock: impl FnMut() -> Duration + 'static) -> Self {
+        self.state.clock = Box::new(clock);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.state.mode = mode;
+        self
+    }
+
+    /// Stops evaluation with `StepLimitExceeded` after `max_steps`
+    /// expressions, so untrusted scripts can't run forever.
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.state.steps_left = Some(max_steps);
+        self
+    }
+
+    /// Writes a line to `output` for every evaluated expression, with its
+    /// kind and result, and counts evaluations per kind (see
+    /// `trace_counts`). Children are written before their parents.
+    pub fn with_trace(mut self, output: Box<dyn Write>) -> Self {
+        self.state.trace = Some(Trace {
+            output,
+            counts: HashMap::new(),
+        });
+        self
+    }
+
+    /// How many times each kind of expression was evaluated, most frequent
+    /// first. Empty unless tracing is enabled.
+    pub fn trace_counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self
+            .state
+            .trace
+            .iter()
+            .flat_map(|trace| trace.counts.iter().map(|(kind, count)| (*kind, *count)))
+            .collect();
+        counts.sort_by(|(a_kind, a), (b_kind, b)| b.cmp(a).then(a_kind.cmp(b_kind)));
+        counts
+    }
+
+    /// Every top-level binding with the type of its current value, sorted
+    /// by name.
+    pub fn defined_names(&self) -> Vec<(String, ValueType)> {
+        let mut names: Vec<_> = self
+            .state
+            .toplevel_scope
+            .globals()
+            .map(|(name, value)| (name.clone(), value.get_type()))
+            .collect();
+        names.sort_by(|(a, _), (b, _)| a.cmp(b));
+        names
+    }
+
+    /// The doc comment of the global function `name`. For overloaded
+    /// functions, that of the first overload that has one.
+    pub fn function_doc(&self, name: &str) -> Option<&str> {
+        match self.state.toplevel_scope.get(name)? {
+            Value::Fn(FnType::User(func)) => func.doc.as_deref(),
+            Value::Fn(FnType::Overloaded(overloads)) => {
+                overloads.iter().find_map(|func| func.doc.as_deref())
+            }
+            _ => None,
+        }
+    }
+
+    /// The code the program passed to `exit`, if it called it.
+    pub fn exit_code(&self) -> Option<Int> {
+        self.state.exit_code
+    }
+
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        run_program(&self.exprs, &mut self.state)
+    }
+
+    /// Evaluates a single expression against the current globals, e.g. one
+    /// line of a REPL. Bindings it makes are kept for later calls. Like
+    /// `run`, an `exit` evaluates to nothing and sets `exit_code`.
+    pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match interpret_expr(expr, &mut self.state) {
+            Err(Flow::Error(RuntimeError::Exit(code))) => {
+                self.state.exit_code = Some(code);
+                Ok(Value::Nothing)
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
+/// Turns any seed, including zero, into a valid xorshift state by running
+/// it through splitmix64.
+pub fn seed_rng(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)).max(1)
+}
+
+/// Runs the top-level statements in order. If the program defines a
+/// top-level `main` function it is called afterwards, without arguments,
+/// and its result is returned; otherwise the result is nothing.
+///
+/// A program ended by `exit` evaluates to nothing, with the code left in
+/// `State::exit_code` for the host to act on.
+pub fn run_program(exprs: &[Expr], state: &mut State) -> Result<Value, RuntimeError> {
+    match run_statements(exprs, state) {
+        Err(RuntimeError::Exit(code)) => {
+            state.exit_code = Some(code);
+            Ok(Value::Nothing)
+        }
+        result => result,
+    }
+}
+
+fn run_statements(exprs: &[Expr], state: &mut State) -> Result<Value, RuntimeError> {
+    // Register every top-level function up front, so functions can be
+    // called before their definition and can call each other
+    for expr in exprs {
+        if let Expr::FnDef { .. } = expr {
+            interpret_expr(expr, state)?;
+        }
+    }
+    for expr in exprs
+        .iter()
+        .filter(|expr| !matches!(expr, Expr::FnDef { .. }))
+    {
+        interpret_expr(expr, state)?;
+    }
+    let has_main = exprs
+        .iter()
+        .any(|expr| matches!(expr, Expr::FnDef { name, .. } if name == "main"));
+    match state.toplevel_scope.get("main") {
+        Some(Value::Fn(main)) if has_main => call_fn(&main.clone(), vec![], state),
+        _ => Ok(Value::Nothing),
+    }
+}
+
+pub fn interpret_expr(expr: &Expr, state: &mut State) -> Result<Value, Flow> {
+    if let Some(steps_left) = &mut state.steps_left {
+        *steps_left = steps_left
+            .checked_sub(1)
+            .ok_or(RuntimeError::StepLimitExceeded)?;
+    }
+    let result = evaluate(expr, state);
+    if let Some(trace) = &mut state.trace {
+        trace.record(expr, &result).map_err(RuntimeError::from)?;
+    }
+    result
+}
+
+fn evaluate(expr: &Expr, state: &mut State) -> Result<Value, Flow> {
+    match expr {
+        Expr::Token(Token::Num(n)) => checked(Int::try_from(*n).ok()),
+        Expr::Token(Token::Float(n)) => Ok(Value::Float(*n)),
+        Expr::Token(Token::String(s)) => Ok(Value::String(s.clone())),
+        Expr::Token(Token::Bool(b)) => Ok(Value::Bool(*b)),
+        Expr::Token(Token::Char(c)) => Ok(Value::Char(*c)),
+        Expr::Token(Token::Identifier(name)) => state
+            .toplevel_scope
+            .get(name)
+            .cloned()
+            .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone()).into()),
+        Expr::BinaryExpr {
+            op: Operator::SetVal,
+            lhs,
+            rhs,
+        } => {
+            let value = interpret_expr(rhs, state)?;
+            match (&**lhs, &value) {
+                (Expr::Tuple(names), Value::Tuple(values))
+                | (Expr::Array(names), Value::Array(values)) => destructure(names, values, state)?,
+                (Expr::Tuple(_), value) => {
+                    return Err(RuntimeError::TypeMismatch {
+                        expected: ValueType::Tuple,
+                        found: value.get_type(),
+                    }
+                    .into())
+                }
+                (Expr::Array(_), value) => {
+                    return Err(RuntimeError::TypeMismatch {
+                        expected: ValueType::Array,
+                        found: value.get_type(),
+                    }
+                    .into())
+                }
+                (Expr::Index { .. }, _) => assign_index(lhs, value.clone(), state)?,
+                _ => state.toplevel_scope.set(&lhs.to_string(), value.clone())?,
+            }
+            Ok(value)
+        }
+        Expr::UnaryExpr {
+            op: Operator::Sub,
+            expr,
+        } => match &**expr {
+            // Literals are negated before they're range checked, the
+            // smallest integer's magnitude doesn't fit on its own
+            Expr::Token(Token::Num(n)) => checked(Int::try_from(-i128::from(*n)).ok()),
+            _ => match interpret_expr(expr, state)? {
+                Value::Int(n) => checked(n.checked_neg()),
+                Value::Float(n) => Ok(Value::Float(-n)),
+                value => Err(RuntimeError::TypeMismatch {
+                    expected: ValueType::Int,
+                    found: value.get_type(),
+                }
+                .into()),
+            },
+        },
+        Expr::UnaryExpr {
+            op: Operator::Not,
+            expr,
+        } => Ok(Value::Bool(!expect_bool(interpret_expr(expr, state)?)?)),
+        Expr::Block(body) => {
+            state.toplevel_scope.enter_block();
+            let result = interpret_block(body, state);
+            state.toplevel_scope.exit_block();
+            result
+        }
+        Expr::Interpolation(parts) => {
+            let mut s = String::new();
+            for part in parts {
+                s.push_str(&interpret_expr(part, state)?.to_string());
+            }
+            Ok(Value::String(s))
+        }
+        Expr::Map(entries) => {
+            let mut map = BTreeMap::new();
+            for (key, value) in entries {
+                let key = map_key(interpret_expr(key, state)?)?;
+                map.insert(key, interpret_expr(value, state)?);
+            }
+            Ok(Value::Map(map))
+        }
+        Expr::Index { expr, index } => {
+            let container = interpret_expr(expr, state)?;
+            let index = interpret_expr(index, state)?;
+            Ok(index_value(container, index)?)
+        }
+        Expr::Field { expr, index } => match interpret_expr(expr, state)? {
+            Value::Tuple(mut values) if *index < values.len() => Ok(values.swap_remove(*index)),
+            Value::Tuple(values) => Err(RuntimeError::IndexOutOfBounds {
+                index: Int::try_from(*index).unwrap_or(Int::MAX),
+                len: values.len(),
+            }
+            .into()),
+            value => Err(RuntimeError::TypeMismatch {
+                expected: ValueType::Tuple,
+                found: value.get_type(),
+            }
+            .into()),
+        },
+        Expr::Tuple(elems) => {
+            let mut values = Vec::new();
+            for elem in elems {
+                values.push(interpret_expr(elem, state)?);
+            }
+            Ok(Value::Tuple(values))
+        }
+        Expr::Array(elems) => {
+            let mut values = Vec::new();
+            for elem in elems {
+                values.push(interpret_expr(elem, state)?);
+            }
+            Ok(Value::Array(values))
+        }
+        Expr::TypedSetVal {
+            name,
+            value_type,
+            expr,
+        } => {
+            let value = interpret_expr(expr, state)?;
+            state
+                .toplevel_scope
+                .set_typed(name, value.clone(), *value_type)?;
+            Ok(value)
+        }
+        Expr::Let {
+            name,
+            value_type,
+            expr,
+        } => {
+            let value = interpret_expr(expr, state)?;
+            state
+                .toplevel_scope
+                .declare(name, value.clone(), *value_type)?;
+            Ok(value)
+        }
+        Expr::Const { name, expr } => {
+            let value = interpret_expr(expr, state)?;
+            state.toplevel_scope.set_const(name, value.clone())?;
+            Ok(value)
+        }
+        Expr::FnDef {
+            name,
+            args,
+            return_type,
+            body,
+            doc,
+        } => {
+            let func = UserFn {
+                name: name.clone(),
+                args: args.clone(),
+                return_type: *return_type,
+                body: body.clone(),
+                doc: doc.clone(),
+            };
+            let func = match state.toplevel_scope.get(name) {
+                Some(Value::Fn(existing)) => add_overload(existing, func),
+                _ => FnType::User(func),
+            };
+            state.toplevel_scope.set(name, Value::Fn(func))?;
+            Ok(Value::Nothing)
+        }
+        Expr::FnCall { name, args } => {
+            let func = match state.toplevel_scope.get(name) {
+                Some(Value::Fn(func)) => func.clone(),
+                Some(_) => return Err(RuntimeError::NotCallable(name.clone()).into()),
+                None => return Err(RuntimeError::UndefinedVariable(name.clone()).into()),
+            };
+            let mut args_vec = Vec::new();
+            let mut named_args = Vec::new();
+            for arg in args {
+                match arg {
+                    Expr::NamedArg { name, expr } => {
+                        named_args.push((name.clone(), interpret_expr(expr, state)?))
+                    }
+                    arg => args_vec.push(interpret_expr(arg, state)?),
+                }
+            }
+            match (&func, named_args.first()) {
+                (_, None) => Ok(call_fn(&func, args_vec, state)?),
+                (FnType::User(func), Some(_)) => {
+                    let args = order_named_args(func, named_args)?;
+                    Ok(call_user_fn(func, args, state)?)
+                }
+                (FnType::Overloaded(overloads), Some(_)) => {
+                    let func = select_overload(overloads, named_args.len())?;
+                    let args = order_named_args(func, named_args)?;
+                    Ok(call_user_fn(func, args, state)?)
+                }
+                (FnType::Builtin(_), Some((name, _))) => {
+                    Err(RuntimeError::UnknownArgument(name.clone()).into())
+                }
+            }
+        }
+        Expr::If {
+            cond,
+            then_body,
+            else_body,
+        } => {
+            let body = if condition(cond, state)? {
+                then_body
+            } else {
+                match else_body {
+                    Some(else_body) => else_body,
+                    None => return Ok(Value::Nothing),
+                }
+            };
+            state.toplevel_scope.enter_block();
+            let result = interpret_block(body, state);
+            state.toplevel_scope.exit_block();
+            result
+        }
+        Expr::While { cond, body } => {
+            while condition(cond, state)? {
+                state.toplevel_scope.enter_block();
+                let result = interpret_block(body, state);
+                state.toplevel_scope.exit_block();
+                match result {
+                    Ok(_) | Err(Flow::Continue) => {}
+                    Err(Flow::Break) => break,
+                    Err(flow) => return Err(flow),
+                }
+            }
+            Ok(Value::Nothing)
+        }
+        Expr::For {
+            var,
+            start,
+            end,
+            body,
+        } => {
+            let (start, end) = match (interpret_expr(start, state)?, interpret_expr(end, state)?) {
+                (Value::Int(start), Value::Int(end)) => (start, end),
+                (Value::Int(_), value) | (value, _) => {
+                    return Err(RuntimeError::TypeMismatch {
+                        expected: ValueType::Int,
+                        found: value.get_type(),
+                    }
+                    .into())
+                }
+            };
+            for i in start..end {
+                // A fresh frame per iteration, so nothing the body binds
+                // leaks into the next one
+                state.toplevel_scope.enter_block();
+                let result = state
+                    .toplevel_scope
+                    .declare(var, Value::Int(i), None)
+                    .map_err(Flow::from)
+                    .and_then(|_| interpret_block(body, state));
+                state.toplevel_scope.exit_block();
+                match result {
+                    Ok(_) | Err(Flow::Continue) => {}
+                    Err(Flow::Break) => break,
+                    Err(flow) => return Err(flow),
+                }
+            }
+            Ok(Value::Nothing)
+        }
+        Expr::Ternary {
+            cond,
+            then_expr,
+            else_expr,
+        } => match interpret_expr(cond, state)? {
+            Value::Bool(true) => interpret_expr(then_expr, state),
+            Value::Bool(false) => interpret_expr(else_expr, state),
+            value => Err(RuntimeError::TypeMismatch {
+                expected: ValueType::Bool,
+                found: value.get_type(),
+            }
+            .into()),
+        },
+        Expr::Match { scrutinee, arms } => {
+            let value = interpret_expr(scrutinee, state)?;
+            for (pattern, body) in arms {
+                let matched = match pattern {
+                    Some(pattern) => {
+                        let pattern = interpret_expr(pattern, state)?;
+                        values_equal(&Operator::Eq, &value, &pattern)?
+                    }
+                    None => true,
+                };
+                if matched {
+                    return interpret_expr(body, state);
+                }
+            }
+            // Without a wildcard arm, nothing matching is not an error
+            Ok(Value::Nothing)
+        }
+        Expr::Return(expr) => Err(Flow::Return(interpret_expr(expr, state)?)),
+        Expr::Break => Err(Flow::Break),
+        Expr::Continue => Err(Flow::Continue),
+        Expr::BinaryExpr { .. } => {
+            // Left-associative chains nest on the left: walk down them in a
+            // loop so a long `1 + 1 + ...` doesn't recurse once per operator
+            let mut operations = Vec::new();
+            let mut leftmost = expr;
+            while let Expr::BinaryExpr { op, lhs, rhs } = leftmost {
+                if *op == Operator::SetVal {
+                    break;
+                }
+                operations.push((leftmost, op, rhs));
+                leftmost = lhs;
+            }
+            let mut value = interpret_expr(leftmost, state)?;
+            for (node, op, rhs) in operations.into_iter().rev() {
+                let result = fold_operator(op, value, rhs, state);
+                // `interpret_expr` records `expr` itself, the nodes folded
+                // into it are recorded here as if they had been evaluated
+                // on their own
+                if !std::ptr::eq(node, expr) {
+                    if let Some(trace) = &mut state.trace {
+                        trace.record(node, &result).map_err(RuntimeError::from)?;
+                    }
+                }
+                value = result?;
+            }
+            Ok(value)
+        }
+        _ => Err(RuntimeError::InvalidExpr(expr.to_string()).into()),
+    }
+}
+
+/// Applies one operator of a left-associative chain to the value so far.
+fn fold_operator(op: &Operator, lhs: Value, rhs: &Expr, state: &mut State) -> Result<Value, Flow> {
+    match op {
+        // `??` only evaluates its right side when the left is nothing
+        Operator::Coalesce if lhs == Value::Nothing => interpret_expr(rhs, state),
+        Operator::Coalesce => Ok(lhs),
+        // `&&` and `||` skip their right side once the left decides the
+        // result
+        Operator::And | Operator::Or => match (op, expect_bool(lhs)?) {
+            (Operator::And, false) | (Operator::Or, true) => Ok(Value::Bool(*op == Operator::Or)),
+            _ => Ok(Value::Bool(expect_bool(interpret_expr(rhs, state)?)?)),
+        },
+        _ => {
+            let rhs = interpret_expr(rhs, state)?;
+            binary_op(op, lhs, rhs, state.mode)
+        }
+    }
+}
+
+fn binary_op(op: &Operator, lhs: Value, rhs: Value, mode: Mode) -> Result<Value, Flow> {
+    let arithmetic = matches!(
+        op,
+        Operator::Add
+            | Operator::Sub
+            | Operator::Mul
+            | Operator::Div
+            | Operator::Mod
+            | Operator::Pow
+    );
+    match coerce(&lhs, &rhs) {
+        Some(Numeric::Int(a, b)) if arithmetic => return int_arithmetic(op, a, b),
+        Some(Numeric::Float(a, b)) if arithmetic => return float_arithmetic(op, a, b),
+        _ => {}
+    }
+    match (op, &lhs, &rhs) {
+        (Operator::BitAnd, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a & b)),
+        (Operator::BitOr, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a | b)),
+        (Operator::BitXor, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a ^ b)),
+        (Operator::Shl | Operator::Shr, Value::Int(a), Value::Int(b)) => {
+            let shift = u32::try_from(*b)
+                .ok()
+                .filter(|shift| *shift < Int::BITS)
+                .ok_or(RuntimeError::InvalidShift(*b))?;
+            match op {
+                // Shifting back has to give `a` again, or bits (or the
+                // sign) were shifted out
+                Operator::Shl => a
+                    .checked_shl(shift)
+                    .filter(|shifted| shifted >> shift == *a)
+                    .map(Value::Int)
+                    .ok_or_else(|| RuntimeError::IntegerOverflow.into()),
+                _ => Ok(Value::Int(a >> shift)),
+            }
+        }
+        (Operator::Add, Value::String(_) | Value::Char(_), Value::String(_) | Value::Char(_)) => {
+            Ok(Value::String(format!("{}{}", lhs, rhs)))
+        }
+        (Operator::Add, Value::String(_), Value::Int(_) | Value::Float(_) | Value::Bool(_))
+        | (Operator::Add, Value::Int(_) | Value::Float(_) | Value::Bool(_), Value::String(_))
+            if mode == Mode::Lenient =>
+        {
+            Ok(Value::String(format!("{}{}", lhs, rhs)))
+        }
+        (
+            Operator::Eq
+            | Operator::Neq
+            | Operator::Lt
+            | Operator::Gt
+            | Operator::Le
+            | Operator::Ge,
+            Value::Int(_) | Value::Float(_),
+            Value::Int(_) | Value::Float(_),
+        ) => Ok(Value::Bool(compare_numbers(op, &lhs, &rhs))),
+        (Operator::Eq, _, _) => Ok(Value::Bool(values_equal(op, &lhs, &rhs)?)),
+        (Operator::Neq, _, _) => Ok(Value::Bool(!values_equal(op, &lhs, &rhs)?)),
+        (
+            Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge,
+            Value::String(a),
+            Value::String(b),
+        ) => Ok(Value::Bool(compare(op, a, b))),
+        (
+            Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge,
+            Value::Char(a),
+            Value::Char(b),
+        ) => Ok(Value::Bool(compare(op, a, b))),
+        _ => Err(RuntimeError::InvalidOperands {
+            op: op.clone(),
+            lhs: lhs.get_type(),
+            rhs: rhs.get_type(),
+        }
+        .into()),
+    }
+}
+
+/// Numeric operands converted to a common type.
+enum Numeric {
+    Int(Int, Int),
+    Float(f64, f64),
+}
+
+/// The coercion policy for arithmetic, kept in one place so every operator
+/// agrees: two ints stay ints, an int paired with a float is promoted to a
+/// float, and nothing else is numeric. In particular bools never turn into
+/// ints, so `1 + true` is an `InvalidOperands` error.
+fn coerce(lhs: &Value, rhs: &Value) -> Option<Numeric> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Some(Numeric::Int(*a, *b)),
+        (Value::Float(a), Value::Float(b)) => Some(Numeric::Float(*a, *b)),
+        (Value::Int(a), Value::Float(b)) => Some(Numeric::Float(*a as f64, *b)),
+        (Value::Float(a), Value::Int(b)) => Some(Numeric::Float(*a, *b as f64)),
+        _ => None,
+    }
+}
+
+/// Compares two numbers of either type. NaN is unordered, so only `!=`
+/// holds for it.
+fn compare_numbers(op: &Operator, lhs: &Value, rhs: &Value) -> bool {
+    let ordering = match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Int(a), Value::Float(b)) => compare_int_float(*a, *b),
+        (Value::Float(a), Value::Int(b)) => compare_int_float(*b, *a).map(Ordering::reverse),
+        _ => unreachable!(),
+    };
+    match (op, ordering) {
+        (Operator::Neq, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => false,
+        (Operator::Eq, Some(ordering)) => ordering == Ordering::Equal,
+        (Operator::Lt, Some(ordering)) => ordering == Ordering::Less,
+        (Operator::Gt, Some(ordering)) => ordering == Ordering::Greater,
+        (Operator::Le, Some(ordering)) => ordering != Ordering::Greater,
+        (Operator::Ge, Some(ordering)) => ordering != Ordering::Less,
+        _ => unreachable!(),
+    }
+}
+
+/// Converting `a` to a float can round it once it's beyond 2^53, which
+/// would make e.g. `9007199254740993 = float(9007199254740992)` true, so a
+/// whole `b` is compared as an integer instead.
+fn compare_int_float(a: Int, b: f64) -> Option<Ordering> {
+    if b.fract() == 0.0 && b >= Int::MIN as f64 && b < Int::MAX as f64 {
+        Some(a.cmp(&(b as Int)))
+    } else {
+        (a as f64).partial_cmp(&b)
+    }
+}
+
+fn int_arithmetic(op: &Operator, a: Int, b: Int) -> Result<Value, Flow> {
+    match op {
+        Operator::Add => checked(a.checked_add(b)),
+        Operator::Sub => checked(a.checked_sub(b)),
+        Operator::Mul => checked(a.checked_mul(b)),
+        Operator::Div if b == 0 => Err(RuntimeError::DivisionByZero.into()),
+        Operator::Div => checked(a.checked_div(b)),
+        Operator::Mod if b == 0 => Err(RuntimeError::DivisionByZero.into()),
+        // The remainder has the sign of `a`. `MIN % -1` is 0, even though
+        // the matching division overflows
+        Operator::Mod => Ok(Value::Int(a.wrapping_rem(b))),
+        Operator::Pow if b < 0 => Err(RuntimeError::NegativeExponent.into()),
+        Operator::Pow => checked(u32::try_from(b).ok().and_then(|b| a.checked_pow(b))),
+        _ => unreachable!(),
+    }
+}
+
+fn float_arithmetic(op: &Operator, a: f64, b: f64) -> Result<Value, Flow> {
+    let value = match op {
+        Operator::Add => a + b,
+        Operator::Sub => a - b,
+        Operator::Mul => a * b,
+        Operator::Div if b == 0.0 => return Err(RuntimeError::DivisionByZero.into()),
+        Operator::Div => a / b,
+        Operator::Mod if b == 0.0 => return Err(RuntimeError::DivisionByZero.into()),
+        Operator::Mod => a % b,
+        Operator::Pow => a.powf(b),
+        _ => unreachable!(),
+    };
+    Ok(Value::Float(value))
+}
+
+/// Calls `func` with positional arguments. The arguments are owned values,
+/// so a call's result can be used like any other operand.
+pub fn call_fn(
+    func: &FnType,
+    passed_args: Vec<Value>,
+    state: &mut State,
+) -> Result<Value, RuntimeError> {
+    let func = match func {
+        FnType::User(func) => func,
+        FnType::Overloaded(overloads) => select_overload(overloads, passed_args.len())?,
+        FnType::Builtin(builtin) => {
+            return call_builtin(&builtin.name, passed_args, builtin.return_type, state)
+        }
+    };
+    let mut args: Vec<_> = passed_args.into_iter().map(Some).collect();
+    // A variadic parameter takes the arguments past the fixed ones
+    if func.args.last().is_some_and(|param| param.variadic) {
+        let fixed = func.args.len() - 1;
+        let rest = args.split_off(fixed.min(args.len())).into_iter().flatten();
+        args.resize(fixed, None);
+        args.push(Some(Value::Array(rest.collect())));
+    } else if args.len() > func.args.len() {
+        return Err(RuntimeError::WrongArgCount {
+            expected: func.args.len(),
+            found: args.len(),
+        });
+    }
+    args.resize(func.args.len(), None);
+    call_user_fn(func, args, state)
+}
+
+/// Defining a function under a name that already holds user functions
+/// overloads it, unless the number of parameters is the same, in which case
+/// the new definition replaces the old one. Builtins are always replaced.
+fn add_overload(existing: &FnType, func: UserFn) -> FnType {
+    let mut overloads = match existing {
+        FnType::User(other) => vec![other.clone()],
+        FnType::Overloaded(overloads) => overloads.clone(),
+        FnType::Builtin(_) => return FnType::User(func),
+    };
+    overloads.retain(|other| other.args.len() != func.args.len());
+    if overloads.is_empty() {
+        return FnType::User(func);
+    }
+    overloads.push(func);
+    overloads.sort_by_key(|func| func.args.len());
+    FnType::Overloaded(overloads)
+}
+
+/// Picks the overload taking exactly `count` arguments, or failing that the
+/// first one that can with its defaults filled in.
+fn select_overload(overloads: &[UserFn], count: usize) -> Result<&UserFn, RuntimeError> {
+    let accepts = |func: &&UserFn| {
+        let required = func
+            .args
+            .iter()
+            .filter(|arg| arg.default.is_none() && !arg.variadic)
+            .count();
+        let variadic = func.args.last().is_some_and(|arg| arg.variadic);
+        count >= required && (variadic || count <= func.args.len())
+    };
+    overloads
+        .iter()
+        .find(|func| func.args.len() == count)
+        .or_else(|| overloads.iter().find(accepts))
+        .ok_or_else(|| RuntimeError::NoOverload {
+            name: overloads[0].name.clone(),
+            found: count,
+            arities: overloads.iter().map(|func| func.args.len()).collect(),
+        })
+}
+
+/// Calls `func` with one slot per parameter; empty slots are filled with
+/// the parameter's default value.
+fn call_user_fn(
+    func: &UserFn,
+    args: Vec<Option<Value>>,
+    state: &mut State,
+) -> Result<Value, RuntimeError> {
+    let caller_start = state.toplevel_scope.enter_function();
+    let result = bind_args(func, args, state).and_then(|_| interpret_block(&func.body, state));
+    state.toplevel_scope.exit_function(caller_start);
+
+    let value = match result {
+        // Like a block, a body that runs to its end evaluates to its last
+        // statement, which a function returning nothing throws away
+        Ok(_) if func.return_type == ValueType::Nothing => Value::Nothing,
+        Ok(value) => value,
+        Err(Flow::Return(value)) => value,
+        Err(flow) => return Err(flow.into()),
+    };
+    let value = coerce_to(func.return_type, value, state.mode)?;
+    Ok(value)
+}
+
+fn bind_args(func: &UserFn, args: Vec<Option<Value>>, state: &mut State) -> Result<(), Flow> {
+    for (param, arg) in func.args.iter().zip(args) {
+        // Defaults are evaluated in the function's scope, so they can refer
+        // to the parameters before them
+        let value = match (arg, &param.default) {
+            (Some(Value::Array(values)), _) if param.variadic => {
+                let values = values
+                    .into_iter()
+                    .map(|value| coerce_to(param.value_type, value, state.mode))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            // Passed by name, it has to be an array already
+            (Some(value), _) if param.variadic => {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: ValueType::Array,
+                    found: value.get_type(),
                 }
-                _ => {}
+                .into())
+            }
+            (None, _) if param.variadic => Value::Array(Vec::new()),
+            (Some(value), _) => coerce_to(param.value_type, value, state.mode)?,
+            (None, Some(default)) => {
+                let value = interpret_expr(default, state)?;
+                coerce_to(param.value_type, value, state.mode)?
             }
+            (None, None) => return Err(RuntimeError::MissingArgument(param.name.clone()).into()),
+        };
+        state.toplevel_scope.set(&param.name, value)?;
+    }
+    Ok(())
+}
+
+/// Puts arguments passed by name into the slots of the matching parameters,
+/// so they can be passed on to `call_user_fn`.
+fn order_named_args(
+    func: &UserFn,
+    named_args: Vec<(String, Value)>,
+) -> Result<Vec<Option<Value>>, RuntimeError> {
+    let mut slots = vec![None; func.args.len()];
+    for (name, value) in named_args {
+        let index = func
+            .args
+            .iter()
+            .position(|param| param.name == name)
+            .ok_or_else(|| RuntimeError::UnknownArgument(name.clone()))?;
+        if slots[index].replace(value).is_some() {
+            return Err(RuntimeError::DuplicateArgument(name));
         }
-        println!("{:?}", self.state.globals);
+    }
+    Ok(slots)
+}
+
+/// Runs `body` in order, evaluating to its last expression.
+fn interpret_block(body: &[Expr], state: &mut State) -> Result<Value, Flow> {
+    let mut value = Value::Nothing;
+    for expr in body {
+        value = interpret_expr(expr, state)?;
+    }
+    Ok(value)
+}
+
+/// Evaluates the condition of an `if` or a loop, which has to be a bool.
+fn condition(cond: &Expr, state: &mut State) -> Result<bool, Flow> {
+    Ok(expect_bool(interpret_expr(cond, state)?)?)
+}
+
+fn expect_bool(value: Value) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => Err(RuntimeError::TypeMismatch {
+            expected: ValueType::Bool,
+            found: value.get_type(),
+        }),
+    }
+}
+
+fn check_type(expected: ValueType, value: &Value) -> Result<(), RuntimeError> {
+    if value.get_type() == expected {
+        Ok(())
+    } else {
+        Err(RuntimeError::TypeMismatch {
+            expected,
+            found: value.get_type(),
+        })
+    }
+}
+
+/// Like `check_type`, but lenient mode lets an int through as a float.
+fn coerce_to(expected: ValueType, value: Value, mode: Mode) -> Result<Value, RuntimeError> {
+    match (expected, value, mode) {
+        (ValueType::Float, Value::Int(n), Mode::Lenient) => Ok(Value::Float(n as f64)),
+        (expected, value, _) => check_type(expected, &value).map(|_| value),
+    }
+}
+
+/// Equality as used by `==`, `!=` and `match`. The two values have to be of
+/// the same type, or both numbers, otherwise it's an error; past that the
+/// comparison is `structurally_equal`, where containers of different
+/// lengths or with elements of different types are just unequal.
+fn values_equal(op: &Operator, lhs: &Value, rhs: &Value) -> Result<bool, RuntimeError> {
+    let numbers = matches!(
+        (lhs, rhs),
+        (
+            Value::Int(_) | Value::Float(_),
+            Value::Int(_) | Value::Float(_)
+        )
+    );
+    if numbers || lhs.get_type() == rhs.get_type() {
+        Ok(structurally_equal(lhs, rhs))
+    } else {
+        Err(RuntimeError::InvalidOperands {
+            op: op.clone(),
+            lhs: lhs.get_type(),
+            rhs: rhs.get_type(),
+        })
+    }
+}
+
+/// Whether two values are equal, comparing numbers by value whatever their
+/// type, also inside containers, so `[1] == [1.0]`. Values of any other
+/// two different types are never equal.
+pub fn structurally_equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            compare_numbers(&Operator::Eq, lhs, rhs)
+        }
+        (Value::Tuple(a), Value::Tuple(b)) | (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| structurally_equal(a, b))
+        }
+        (Value::Map(a), Value::Map(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((ka, va), (kb, vb))| ka == kb && structurally_equal(va, vb))
+        }
+        _ => lhs == rhs,
+    }
+}
+
+/// Binds each name in a pattern to the value in the same position, and a
+/// trailing `rest...` to an array of the values after those.
+fn destructure(names: &[Expr], values: &[Value], state: &mut State) -> Result<(), RuntimeError> {
+    let (names, rest) = match names.split_last() {
+        Some((Expr::Rest(rest), names)) => (names, Some(rest)),
+        _ => (names, None),
+    };
+    if values.len() < names.len() || (rest.is_none() && values.len() != names.len()) {
+        return Err(RuntimeError::DestructureMismatch {
+            expected: names.len(),
+            found: values.len(),
+            rest: rest.is_some(),
+        });
+    }
+    for (name, value) in names.iter().zip(values) {
+        state.toplevel_scope.set(&name.to_string(), value.clone())?;
+    }
+    if let Some(rest) = rest {
+        let rest_values = values[names.len()..].to_vec();
+        state.toplevel_scope.set(rest, Value::Array(rest_values))?;
+    }
+    Ok(())
+}
+
+/// Stores `value` at `target`, an `Index` expression. The container is
+/// read, updated and written back through its own target, so nested
+/// indexing like `m["a"]["b"] := 1` works.
+fn assign_index(target: &Expr, value: Value, state: &mut State) -> Result<(), Flow> {
+    match target {
+        Expr::Index { expr, index } => {
+            let index = interpret_expr(index, state)?;
+            let container = match interpret_expr(expr, state)? {
+                Value::Map(mut map) => {
+                    map.insert(map_key(index)?, value);
+                    Value::Map(map)
+                }
+                Value::Array(mut values) => {
+                    let i = resolve_index(int_index(index)?, values.len())?;
+                    values[i] = value;
+                    Value::Array(values)
+                }
+                other => {
+                    return Err(RuntimeError::TypeMismatch {
+                        expected: ValueType::Array,
+                        found: other.get_type(),
+                    }
+                    .into())
+                }
+            };
+            assign_index(expr, container, state)
+        }
+        Expr::Token(Token::Identifier(name)) => Ok(state.toplevel_scope.set(name, value)?),
+        other => Err(RuntimeError::InvalidExpr(other.to_string()).into()),
+    }
+}
+
+/// `container[index]` for maps, arrays and strings.
+fn index_value(container: Value, index: Value) -> Result<Value, RuntimeError> {
+    match container {
+        Value::Map(mut map) => {
+            let key = map_key(index)?;
+            map.remove(&key).ok_or(RuntimeError::MissingKey(key))
+        }
+        Value::Array(mut values) => {
+            let i = resolve_index(int_index(index)?, values.len())?;
+            Ok(values.swap_remove(i))
+        }
+        Value::String(s) => {
+            let chars: Vec<_> = s.chars().collect();
+            let i = resolve_index(int_index(index)?, chars.len())?;
+            Ok(Value::Char(chars[i]))
+        }
+        other => Err(RuntimeError::TypeMismatch {
+            expected: ValueType::Array,
+            found: other.get_type(),
+        }),
+    }
+}
+
+/// Turns `index` into a position in a sequence of `len` elements. Negative
+/// indices count from the end, `-1` being the last element.
+pub fn resolve_index(index: Int, len: usize) -> Result<usize, RuntimeError> {
+    let resolved = if index < 0 {
+        len.checked_sub(index.unsigned_abs() as usize)
+    } else {
+        usize::try_from(index).ok()
+    };
+    resolved
+        .filter(|i| *i < len)
+        .ok_or(RuntimeError::IndexOutOfBounds { index, len })
+}
+
+fn int_index(index: Value) -> Result<Int, RuntimeError> {
+    match index {
+        Value::Int(index) => Ok(index),
+        other => Err(RuntimeError::TypeMismatch {
+            expected: ValueType::Int,
+            found: other.get_type(),
+        }),
+    }
+}
+
+fn map_key(key: Value) -> Result<String, RuntimeError> {
+    match key {
+        Value::String(key) => Ok(key),
+        other => Err(RuntimeError::TypeMismatch {
+            expected: ValueType::String,
+            found: other.get_type(),
+        }),
+    }
+}
+
+fn checked(result: Option<Int>) -> Result<Value, Flow> {
+    result
+        .map(Value::Int)
+        .ok_or_else(|| RuntimeError::IntegerOverflow.into())
+}
+
+fn compare<T: PartialOrd>(op: &Operator, a: T, b: T) -> bool {
+    match op {
+        Operator::Lt => a < b,
+        Operator::Gt => a > b,
+        Operator::Le => a <= b,
+        Operator::Ge => a >= b,
+        _ => unreachable!(),
     }
 }
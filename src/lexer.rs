@@ -1,25 +1,119 @@
-use {logos::Logos, std::fmt::Display, substring::Substring};
+use {
+    logos::{FilterResult, Lexer, Logos, SpannedIter},
+    std::{fmt::Display, ops::Range},
+    substring::Substring,
+};
 
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
     #[token(":=")]
     SetVal,
 
+    /// `+=` and friends, holding the operator without the `=`.
+    #[regex(r"[+\-*/%]=", |lex| lex.slice().trim_end_matches('=').to_string())]
+    CompoundSet(String),
+
     #[token("=")]
     Equal,
 
     #[token("!=")]
     NotEqual,
 
-    #[regex(r#""([^"\\]|\\t|\\u|\\n|\\")*""#, |lex| lex.slice().parse::<String>().unwrap().substring(1, lex.slice().len() - 1).to_string())]
+    #[token("!")]
+    Not,
+
+    #[token("const")]
+    Const,
+
+    #[token("let")]
+    Let,
+
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("func")]
+    Func,
+
+    #[token("match")]
+    Match,
+
+    #[token("if")]
+    If,
+
+    #[token("else")]
+    Else,
+
+    #[token("while")]
+    While,
+
+    #[token("for")]
+    For,
+
+    #[token("in")]
+    In,
+
+    #[token("..")]
+    DotDot,
+
+    /// `.0` after a tuple, lexed as one token so `t.0.1` isn't a float.
+    #[regex(r"\.[0-9]+", |lex| lex.slice()[1..].parse())]
+    Field(usize),
+
+    #[token("=>")]
+    FatArrow,
+
+    #[token("_")]
+    Underscore,
+
+    #[token("return")]
+    Return,
+
+    #[token("->")]
+    Arrow,
+
+    #[token("true", |_| true)]
+    #[token("false", |_| false)]
+    Bool(bool),
+
+    #[regex("int|float|string|bool|char|fn|tuple|array|map|builder|nothing", |lex| lex.slice().to_string())]
+    Type(String),
+
+    /// Escapes are replaced in quoted strings, raw and multiline strings are
+    /// kept verbatim.
+    #[regex(r#""([^"\\{}]|\\.)*""#, priority = 10, callback = |lex| unescape_str(&lex.slice()[1..lex.slice().len() - 1]).ok())]
+    #[regex(r#"r"[^"]*""#, |lex| lex.slice().substring(2, lex.slice().chars().count() - 1).to_string())]
+    #[token("\"\"\"", lex_multiline)]
     String(String),
 
-    #[regex("[a-zA-AZ]+", |lex| lex.slice().to_string())]
+    /// A string with `{expr}` holes, kept as its raw contents for the
+    /// parser to split up. Escapes are only checked here and replaced in
+    /// the text between the holes, so `\u{7B}` can't open one.
+    #[regex(r#""([^"\\]|\\.)*""#, |lex| raw_contents(lex.slice()))]
+    InterpString(String),
+
+    #[regex(r"'([^'\\]|\\.|\\u\{[0-9a-fA-F]*\})'", |lex| parse_char(lex.slice()))]
+    Char(char),
+
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
     Identifier(String),
 
-    #[regex("[0-9]+", |lex| lex.slice().parse())]
+    /// Decimal, `0x` hex, `0b` binary or `0o` octal, with `_` allowed
+    /// between digits as a separator.
+    #[regex("[0-9][0-9_]*", priority = 2, callback = |lex| parse_int(lex.slice(), 10))]
+    #[regex("0x[0-9a-fA-F_]+", |lex| parse_int(&lex.slice()[2..], 16))]
+    #[regex("0b[01_]+", |lex| parse_int(&lex.slice()[2..], 2))]
+    #[regex("0o[0-7_]+", |lex| parse_int(&lex.slice()[2..], 8))]
     Num(u64),
 
+    /// Digits are required on both sides of the dot, so `0..10` is still a
+    /// range. Literals too big for a float are an error, not infinity.
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?|[0-9][0-9_]*[eE][+-]?[0-9][0-9_]*", |lex| lex.slice().replace('_', "").parse().ok().filter(|n: &f64| n.is_finite()))]
+    Float(f64),
+
     #[token("(")]
     LParen,
 
@@ -41,11 +135,40 @@ pub enum Token {
     #[token(";")]
     Semicolon,
 
-    #[regex(r"[+\-*/]", |lex| lex.slice().to_string())]
+    #[token(",")]
+    Comma,
+
+    #[token("?")]
+    Question,
+
+    #[token(":")]
+    Colon,
+
+    #[token("...")]
+    Ellipsis,
+
+    #[regex(r"[+\-*/%<>&|^]|==|<=|>=|<<|>>|\*\*|\?\?|&&|\|\|", |lex| lex.slice().to_string())]
     Operator(String),
 
+    /// A `///` comment line, without the slashes and the space after them.
+    #[regex(r"///[^\n]*", priority = 10, callback = |lex| doc_comment(lex.slice()))]
+    DocComment(String),
+
+    /// A line break between two tokens, only produced by
+    /// `lex_with_newlines`.
+    Newline,
+
     #[error]
-    #[regex(r"[ \n\t\f]+", logos::skip)]
+    // A run of operator characters that isn't an operator, like `@@`, is
+    // one error rather than an error per character
+    #[regex(r"[@$~][+\-*/<>?!=@$%^&|~]*", |_| false)]
+    // Same for a number running into letters, like `0x` or `0b102`
+    #[regex("[0-9][0-9a-zA-Z_]*", priority = 1)]
+    // Whitespace (including newlines and `\r\n` line endings) only separates
+    // tokens, so expressions can be split across lines anywhere
+    #[regex(r"[ \t\r\n\f]+", logos::skip)]
+    #[regex(r"//[^\n]*", logos::skip)]
+    #[token("/*", block_comment)]
     Error,
 }
 
@@ -53,11 +176,35 @@ impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::SetVal => write!(f, ":="),
+            Token::CompoundSet(op) => write!(f, "{}=", op),
             Token::Equal => write!(f, "="),
             Token::NotEqual => write!(f, "!="),
+            Token::Not => write!(f, "!"),
+            Token::Const => write!(f, "const"),
+            Token::Let => write!(f, "let"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Func => write!(f, "func"),
+            Token::Match => write!(f, "match"),
+            Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::In => write!(f, "in"),
+            Token::DotDot => write!(f, ".."),
+            Token::Field(index) => write!(f, ".{}", index),
+            Token::FatArrow => write!(f, "=>"),
+            Token::Underscore => write!(f, "_"),
+            Token::Return => write!(f, "return"),
+            Token::Arrow => write!(f, "->"),
+            Token::Bool(b) => write!(f, "{}", b),
+            Token::Type(s) => write!(f, "{}", s),
             Token::String(s) => write!(f, "{}", s),
+            Token::InterpString(s) => write!(f, "\"{}\"", s),
+            Token::Char(c) => write!(f, "'{}'", c.escape_default()),
             Token::Identifier(s) => write!(f, "{}", s),
             Token::Num(n) => write!(f, "{}", n),
+            Token::Float(n) => write!(f, "{:?}", n),
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
             Token::LBrace => write!(f, "{{"),
@@ -65,8 +212,327 @@ impl Display for Token {
             Token::LBracket => write!(f, "["),
             Token::RBracket => write!(f, "]"),
             Token::Semicolon => write!(f, ";"),
+            Token::Comma => write!(f, ","),
+            Token::Question => write!(f, "?"),
+            Token::Colon => write!(f, ":"),
+            Token::Ellipsis => write!(f, "..."),
             Token::Operator(s) => write!(f, "{}", s),
+            Token::DocComment(doc) => write!(f, "/// {}", doc),
+            Token::Newline => writeln!(f),
             Token::Error => write!(f, "Error"),
         }
     }
 }
+
+/// Input the lexer couldn't make a token out of, with the byte range it
+/// covers in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedChar(char, Range<usize>),
+    UnterminatedString(Range<usize>),
+    UnterminatedComment(Range<usize>),
+    /// A backslash sequence that isn't a known escape, like `\q`.
+    InvalidEscape(String, Range<usize>),
+    /// An integer literal too big for 64 bits, or a float literal too big
+    /// for a float.
+    InvalidNumber(String, Range<usize>),
+    UnknownOperator(String, Range<usize>),
+}
+
+impl LexError {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            LexError::UnexpectedChar(_, span)
+            | LexError::UnterminatedString(span)
+            | LexError::UnterminatedComment(span)
+            | LexError::InvalidEscape(_, span)
+            | LexError::InvalidNumber(_, span)
+            | LexError::UnknownOperator(_, span) => span.clone(),
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::UnexpectedChar(c, _) => write!(f, "unexpected character '{}'", c),
+            LexError::UnterminatedString(_) => write!(f, "unterminated string literal"),
+            LexError::UnterminatedComment(_) => write!(f, "unterminated block comment"),
+            LexError::InvalidEscape(escape, _) => write!(f, "invalid escape '{}'", escape),
+            LexError::InvalidNumber(number, _) => write!(f, "invalid number '{}'", number),
+            LexError::UnknownOperator(op, _) => write!(f, "unknown operator '{}'", op),
+        }
+    }
+}
+
+pub fn lex(source: &str) -> Result<Vec<Token>, LexError> {
+    TokenStream::new(source).collect()
+}
+
+/// Like `lex`, but keeps the line breaks between tokens as `Newline`
+/// tokens.
+pub fn lex_with_newlines(source: &str) -> Result<Vec<Token>, LexError> {
+    TokenStream::new(source).with_newlines().collect()
+}
+
+/// Like `lex_with_newlines`, with the byte range of the source each token
+/// came from. A `Newline` covers the whitespace between the tokens around
+/// it.
+pub fn lex_spanned(source: &str) -> Result<Vec<(Token, Range<usize>)>, LexError> {
+    let mut stream = TokenStream::new(source).with_newlines();
+    let mut tokens = Vec::new();
+    while let Some(token) = stream.next() {
+        tokens.push((token?, stream.span()));
+    }
+    Ok(tokens)
+}
+
+/// Lexes `source` lazily, a token at a time, ending after the first error.
+/// A caller that only scans tokens never holds the whole list; the parser
+/// still buffers everything, see `Parser::from_tokens`.
+pub struct TokenStream<'a> {
+    source: &'a str,
+    tokens: SpannedIter<'a, Token>,
+    keep_newlines: bool,
+    /// Where the last token ended, to look for line breaks after it.
+    end: usize,
+    /// The source range of the token returned last.
+    span: Range<usize>,
+    /// The token after a `Newline` that has just been returned.
+    pending: Option<(Token, Range<usize>)>,
+    failed: bool,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(source: &'a str) -> Self {
+        TokenStream {
+            source,
+            tokens: Token::lexer(source).spanned(),
+            keep_newlines: false,
+            end: 0,
+            span: 0..0,
+            pending: None,
+            failed: false,
+        }
+    }
+
+    pub fn with_newlines(mut self) -> Self {
+        self.keep_newlines = true;
+        self
+    }
+
+    /// The byte range of the token `next` returned last.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((token, span)) = self.pending.take() {
+            self.span = span;
+            return Some(Ok(token));
+        }
+        if self.failed {
+            return None;
+        }
+        let (token, span) = self.tokens.next()?;
+        if token == Token::Error {
+            self.failed = true;
+            return Some(Err(lex_error(self.source, span)));
+        }
+        let gap = self.end..span.start;
+        self.end = span.end;
+        if self.keep_newlines && self.source[gap.clone()].contains('\n') {
+            self.pending = Some((token, span));
+            self.span = gap;
+            return Some(Ok(Token::Newline));
+        }
+        self.span = span;
+        Some(Ok(token))
+    }
+}
+
+/// Works out what went wrong from the input logos gave up on.
+fn lex_error(source: &str, span: Range<usize>) -> LexError {
+    let input = &source[span.clone()];
+    // A complete literal only fails because of a bad escape
+    let quoted = input.len() >= 2
+        && !input.starts_with("\"\"\"")
+        && ((input.starts_with('"') && input.ends_with('"'))
+            || (input.starts_with('\'') && input.ends_with('\'')));
+    if let Some(Err(escape)) = quoted.then(|| unescape_str(&input[1..input.len() - 1])) {
+        // Past the opening quote
+        let escape = escape.start + 1..escape.end + 1;
+        LexError::InvalidEscape(
+            input[escape.clone()].to_string(),
+            span.start + escape.start..span.start + escape.end,
+        )
+    } else if input.starts_with('"') || input.starts_with("r\"") {
+        LexError::UnterminatedString(span)
+    } else if input.starts_with("/*") {
+        LexError::UnterminatedComment(span)
+    } else if input.starts_with(|c: char| c.is_ascii_digit()) {
+        LexError::InvalidNumber(input.to_string(), span)
+    } else if input.starts_with(|c: char| "+-*/<>?!=@$%~".contains(c)) {
+        LexError::UnknownOperator(input.to_string(), span)
+    } else {
+        LexError::UnexpectedChar(input.chars().next().unwrap(), span)
+    }
+}
+
+/// Multiline strings run until the next `"""`, everything in between is
+/// kept verbatim.
+fn lex_multiline(lex: &mut Lexer<Token>) -> Option<String> {
+    let end = lex.remainder().find("\"\"\"")?;
+    let contents = lex.remainder()[..end].to_string();
+    lex.bump(end + 3);
+    Some(contents)
+}
+
+/// Block comments run until the next `*/`, they don't nest.
+fn block_comment(lex: &mut Lexer<Token>) -> FilterResult<()> {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            FilterResult::Skip
+        }
+        None => FilterResult::Error,
+    }
+}
+
+fn parse_int(digits: &str, radix: u32) -> Option<u64> {
+    u64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+fn doc_comment(slice: &str) -> String {
+    let doc = &slice[3..];
+    doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string()
+}
+
+fn parse_char(slice: &str) -> Option<char> {
+    let contents = unescape_str(&slice[1..slice.len() - 1]).ok()?;
+    let mut chars = contents.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// The contents of a quoted literal, if its escapes are all valid.
+fn raw_contents(slice: &str) -> Option<String> {
+    let contents = &slice[1..slice.len() - 1];
+    unescape_str(contents).ok()?;
+    Some(contents.to_string())
+}
+
+/// Replaces the escapes in a literal's contents: the ones `unescape`
+/// knows, and `\u{...}` with 1 to 6 hex digits. An invalid escape is
+/// returned as its byte range in `s`.
+pub fn unescape_str(s: &str) -> Result<String, Range<usize>> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(backslash) = rest.find('\\') {
+        unescaped.push_str(&rest[..backslash]);
+        let escape = &rest[backslash..];
+        let len = match escape[1..].chars().next() {
+            Some('u') => escape.find('}').map_or(2, |end| end + 1),
+            Some(c) => 1 + c.len_utf8(),
+            None => 1,
+        };
+        let c = match escape[1..len].strip_prefix('u') {
+            Some(code) => code
+                .strip_prefix('{')
+                .and_then(|code| code.strip_suffix('}'))
+                .filter(|code| {
+                    (1..=6).contains(&code.len()) && code.chars().all(|c| c.is_ascii_hexdigit())
+                })
+                .and_then(|code| u32::from_str_radix(code, 16).ok())
+                .and_then(char::from_u32),
+            None => escape[1..len].chars().next().and_then(unescape),
+        };
+        let start = s.len() - escape.len();
+        unescaped.push(c.ok_or(start..start + len)?);
+        rest = &escape[len..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+/// Maps the character after a backslash to the character it stands for.
+pub fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "serde")]
+pub fn to_json(tokens: &[Token]) -> String {
+    serde_json::to_string(tokens).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_multiline_strings_report_where_they_start() {
+        let source = "x := 1;\ns := \"\"\"never\nclosed";
+        match lex(source) {
+            Err(LexError::UnterminatedString(span)) => assert_eq!(span.start, 13),
+            other => panic!("expected an unterminated string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_operators_are_reported_where_they_are() {
+        match lex("x := 1 @@ 2;") {
+            Err(err @ LexError::UnknownOperator(..)) => {
+                assert_eq!(err.to_string(), "unknown operator '@@'");
+                assert_eq!(err.span(), 7..9);
+            }
+            other => panic!("expected an unknown operator, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn each_kind_of_bad_input_has_its_own_error() {
+        let too_big = "1".repeat(30);
+        let cases = [
+            ("x := 1 # 2;", LexError::UnexpectedChar('#', 7..8)),
+            ("s := \"open;", LexError::UnterminatedString(5..11)),
+            ("x := 1; /* open", LexError::UnterminatedComment(8..10)),
+            (
+                "s := \"a\\qb\";",
+                LexError::InvalidEscape("\\q".to_string(), 7..9),
+            ),
+            (
+                too_big.as_str(),
+                LexError::InvalidNumber(too_big.clone(), 0..30),
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(lex(source), Err(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn a_stream_yields_tokens_lazily_and_stops_at_an_error() {
+        let mut stream = TokenStream::new("x := 1 # 2;");
+        assert_eq!(stream.next(), Some(Ok(Token::Identifier("x".to_string()))));
+        assert_eq!(stream.span(), 0..1);
+        assert_eq!(stream.nth(1), Some(Ok(Token::Num(1))));
+        assert_eq!(
+            stream.next(),
+            Some(Err(LexError::UnexpectedChar('#', 7..8)))
+        );
+        assert_eq!(stream.next(), None);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod builtins;
+pub mod error;
+pub mod interpreter;
+pub mod lexer;
+pub mod lint;
+pub mod parser;
+pub mod program;
+pub mod resolve;
+pub mod visitor;
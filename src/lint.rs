@@ -0,0 +1,227 @@
+use {
+    crate::{
+        parser::Expr,
+        visitor::{walk, Visitor},
+    },
+    std::fmt::Display,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Flags statements that follow a `return` in the same block, be it a
+/// function body or the body of an `if`, a loop or a block expression.
+/// Only a `return` directly in the block counts, one nested inside another
+/// expression (a ternary, a match arm) may not run.
+pub fn check_unreachable(exprs: &[Expr], severity: Severity) -> Vec<Diagnostic> {
+    let mut check = UnreachableCheck {
+        severity,
+        function: None,
+        diagnostics: Vec::new(),
+    };
+    for expr in exprs {
+        check.visit_expr(expr);
+    }
+    check.diagnostics
+}
+
+struct UnreachableCheck {
+    severity: Severity,
+    /// The innermost function being checked, to name it in the message.
+    function: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Visitor for UnreachableCheck {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::If {
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.check_block(then_body);
+                if let Some(else_body) = else_body {
+                    self.check_block(else_body);
+                }
+            }
+            Expr::While { body, .. } | Expr::For { body, .. } | Expr::Block(body) => {
+                self.check_block(body)
+            }
+            _ => {}
+        }
+        walk(self, expr)
+    }
+
+    fn visit_fn_def(&mut self, name: &str, body: &[Expr]) {
+        let outer = self.function.replace(name.to_string());
+        self.check_block(body);
+        for expr in body {
+            self.visit_expr(expr);
+        }
+        self.function = outer;
+    }
+}
+
+impl UnreachableCheck {
+    fn check_block(&mut self, body: &[Expr]) {
+        let first_return = body.iter().position(|expr| matches!(expr, Expr::Return(_)));
+        if let Some(unreachable) = first_return.and_then(|i| body.get(i + 1)) {
+            let message = match &self.function {
+                Some(name) => format!(
+                    "unreachable statement after return in function '{}': {}",
+                    name, unreachable
+                ),
+                None => format!("unreachable statement after return: {}", unreachable),
+            };
+            self.diagnostics.push(Diagnostic {
+                severity: self.severity,
+                message,
+            });
+        }
+    }
+}
+
+/// Flags `break` and `continue` outside of a loop body. A function body
+/// starts outside of any loop, even if the function is called from one.
+pub fn check_loop_control(exprs: &[Expr], severity: Severity) -> Vec<Diagnostic> {
+    let mut check = LoopControlCheck {
+        severity,
+        loop_depth: 0,
+        diagnostics: Vec::new(),
+    };
+    for expr in exprs {
+        check.visit_expr(expr);
+    }
+    check.diagnostics
+}
+
+struct LoopControlCheck {
+    severity: Severity,
+    loop_depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Visitor for LoopControlCheck {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::While { cond, body } => {
+                self.visit_expr(cond);
+                self.visit_loop_body(body);
+            }
+            Expr::For {
+                start, end, body, ..
+            } => {
+                self.visit_expr(start);
+                self.visit_expr(end);
+                self.visit_loop_body(body);
+            }
+            Expr::Break | Expr::Continue if self.loop_depth == 0 => {
+                self.diagnostics.push(Diagnostic {
+                    severity: self.severity,
+                    message: format!("'{}' outside of a loop", expr),
+                });
+            }
+            expr => walk(self, expr),
+        }
+    }
+
+    fn visit_fn_def(&mut self, _name: &str, body: &[Expr]) {
+        let outer = std::mem::replace(&mut self.loop_depth, 0);
+        for expr in body {
+            self.visit_expr(expr);
+        }
+        self.loop_depth = outer;
+    }
+}
+
+impl LoopControlCheck {
+    fn visit_loop_body(&mut self, body: &[Expr]) {
+        self.loop_depth += 1;
+        for expr in body {
+            self.visit_expr(expr);
+        }
+        self.loop_depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{lexer::lex, parser::Parser},
+    };
+
+    fn unreachable(source: &str) -> Vec<String> {
+        let exprs = Parser::new(lex(source).unwrap()).parse().unwrap();
+        check_unreachable(&exprs, Severity::Warning)
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn flags_statements_after_a_return() {
+        assert_eq!(
+            unreachable(r#"func f() -> int { return 1; print([2], "a"); }"#),
+            [r#"warning: unreachable statement after return in function 'f': print([2], "a")"#]
+        );
+    }
+
+    #[test]
+    fn a_return_in_a_branch_leaves_the_rest_reachable() {
+        assert!(
+            unreachable("func f(int x) -> int { if x > 0 { return 1; } return 2; }").is_empty()
+        );
+        assert!(unreachable("func f(int x) -> int { return x > 0 ? 1 : 2; }").is_empty());
+    }
+
+    #[test]
+    fn checks_nested_functions() {
+        assert_eq!(
+            unreachable("func f() { func g() -> int { return 1; x := 1; } }").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn flags_statements_after_a_return_in_nested_blocks() {
+        assert_eq!(
+            unreachable("func f() -> int { if true { return 1; print(2); } return 3; }"),
+            ["warning: unreachable statement after return in function 'f': print(2)"]
+        );
+        assert_eq!(
+            unreachable(
+                "func f() -> int { while true { return 1; x := 1; } for i in 0..3 { return 2; y := 2; } return 0; }"
+            )
+            .len(),
+            2
+        );
+        assert_eq!(
+            unreachable("func f() -> int { if false { return 1; } else { return 2; print(3); } }"),
+            ["warning: unreachable statement after return in function 'f': print(3)"]
+        );
+        assert_eq!(
+            unreachable("func f() -> int { x := { return 1; 2 }; return x; }").len(),
+            1
+        );
+    }
+}
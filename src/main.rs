@@ -1,12 +1,6 @@
-mod interpreter;
-mod lexer;
-mod parser;
 use {
-    interpreter::Interpreter,
-    lexer::Token,
-    logos::Logos,
-    parser::Parser,
-    std::{fs::File, io::Read},
+    pulsar::{error::render_error, interpreter::Interpreter, program::Program},
+    std::{fs::File, io::Read, process},
 };
 
 fn read_file() -> String {
@@ -17,8 +11,23 @@ fn read_file() -> String {
 }
 
 fn main() {
-    let lex: Vec<_> = Token::lexer(&read_file()).collect();
-    let exprs: Vec<_> = Parser::new(lex).parse();
-    let mut interpreter: Interpreter = Interpreter::new(exprs);
-    interpreter.run();
+    let source = read_file();
+    let program = match Program::compile(&source) {
+        Ok(program) => program,
+        Err(err) => {
+            eprint!("{}", render_error(&source, &err));
+            return;
+        }
+    };
+    for warning in &program.warnings {
+        eprintln!("{}", warning);
+    }
+    let mut interpreter = Interpreter::new(Vec::new());
+    if let Err(err) = program.run_with(&mut interpreter) {
+        eprint!("{}", render_error(&source, &err));
+    }
+    if let Some(code) = interpreter.exit_code() {
+        // Only the low 8 bits make it to the shell anyway
+        process::exit(i32::from(code as u8));
+    }
 }
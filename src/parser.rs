@@ -1,20 +1,112 @@
-use std::{fmt::Display, iter::Peekable, slice::Iter};
+use std::{fmt::Display, iter::Peekable, ops::Range, slice::Iter, str::FromStr};
 
-use crate::lexer::Token;
+use crate::{
+    interpreter::{get_valuetype_from, ValueType},
+    lexer::{lex, unescape_str, Token},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parser {
     tokens: Vec<Token>,
+    /// The source range of each token, empty if unknown.
+    spans: Vec<Range<usize>>,
+    options: ParserOptions,
+}
+
+/// What the parser functions return: the parsed node and the tokens after
+/// it.
+type Parsed<'a, T> = Result<(T, &'a mut Peekable<Iter<'a, Token>>), ParseError>;
+
+/// Tokens that don't make up a valid program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// The source range of the token the parser stopped at, when the
+    /// parser was given spans.
+    pub span: Option<Range<usize>>,
+    /// How many tokens there are from the offending one to the end, until
+    /// `parse` turns it into a span.
+    left: usize,
+}
+
+impl ParseError {
+    /// An error at the token `tokens` would return next.
+    fn at(tokens: &Peekable<Iter<Token>>, message: impl Into<String>) -> Self {
+        ParseError {
+            message: message.into(),
+            span: None,
+            left: tokens.len(),
+        }
+    }
+
+    /// An error at the token `tokens` returned last.
+    fn after(tokens: &Peekable<Iter<Token>>, message: impl Into<String>) -> Self {
+        ParseError {
+            left: tokens.len() + 1,
+            ..ParseError::at(tokens, message)
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParserOptions {
+    pub statement_terminator: Terminator,
+    pub mode: Mode,
+}
+
+/// How forgiving the parser and interpreter are with borderline programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Strict,
+    /// Line ends terminate statements as with `Terminator::Newline`, `+`
+    /// turns numbers and bools into text when the other side is a string,
+    /// and ints are accepted where a float parameter or return value is
+    /// expected.
+    Lenient,
+}
+
+/// What ends a statement.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Terminator {
+    #[default]
+    Semicolon,
+    /// A line break ends a statement too, unless it's inside parentheses or
+    /// brackets or the line can't end there (e.g. after a binary operator).
+    /// Needs the tokens from `lex_with_newlines`.
+    Newline,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
+    Mod,
+    Pow,
     Eq,
     Neq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Coalesce,
+    And,
+    Or,
+    Not,
     SetVal,
 }
 
@@ -25,14 +117,115 @@ impl Display for Operator {
             Operator::Sub => write!(f, "-"),
             Operator::Mul => write!(f, "*"),
             Operator::Div => write!(f, "/"),
-            Operator::Eq => write!(f, "="),
+            Operator::Mod => write!(f, "%"),
+            Operator::Pow => write!(f, "**"),
+            Operator::Eq => write!(f, "=="),
             Operator::Neq => write!(f, "!="),
+            Operator::Lt => write!(f, "<"),
+            Operator::Gt => write!(f, ">"),
+            Operator::Le => write!(f, "<="),
+            Operator::Ge => write!(f, ">="),
+            Operator::BitAnd => write!(f, "&"),
+            Operator::BitOr => write!(f, "|"),
+            Operator::BitXor => write!(f, "^"),
+            Operator::Shl => write!(f, "<<"),
+            Operator::Shr => write!(f, ">>"),
+            Operator::Coalesce => write!(f, "??"),
+            Operator::And => write!(f, "&&"),
+            Operator::Or => write!(f, "||"),
+            Operator::Not => write!(f, "!"),
             Operator::SetVal => write!(f, ":="),
         }
     }
 }
 
+impl Operator {
+    /// How tightly the operator binds, higher binds tighter. Operators are
+    /// left-associative unless `is_right_assoc` says otherwise. Unary `-`
+    /// binds between `*` and `**`, so `-2 ** 2` is `-(2 ** 2)`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::SetVal => 0,
+            Operator::Coalesce => 1,
+            Operator::Or => 2,
+            Operator::And => 3,
+            // Like C, the bitwise operators bind looser than comparisons
+            Operator::BitOr => 4,
+            Operator::BitXor => 5,
+            Operator::BitAnd => 6,
+            Operator::Eq | Operator::Neq => 7,
+            Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge => 8,
+            Operator::Shl | Operator::Shr => 9,
+            Operator::Add | Operator::Sub => 10,
+            Operator::Mul | Operator::Div | Operator::Mod => 11,
+            Operator::Pow => 12,
+            // Only ever unary, binding tighter than any binary operator
+            Operator::Not => 13,
+        }
+    }
+
+    pub fn is_right_assoc(&self) -> bool {
+        matches!(self, Operator::Pow | Operator::Coalesce | Operator::SetVal)
+    }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(Operator::Add),
+            "-" => Ok(Operator::Sub),
+            "*" => Ok(Operator::Mul),
+            "/" => Ok(Operator::Div),
+            "%" => Ok(Operator::Mod),
+            "**" => Ok(Operator::Pow),
+            "==" => Ok(Operator::Eq),
+            "!=" => Ok(Operator::Neq),
+            "<" => Ok(Operator::Lt),
+            ">" => Ok(Operator::Gt),
+            "<=" => Ok(Operator::Le),
+            ">=" => Ok(Operator::Ge),
+            "&" => Ok(Operator::BitAnd),
+            "|" => Ok(Operator::BitOr),
+            "^" => Ok(Operator::BitXor),
+            "<<" => Ok(Operator::Shl),
+            ">>" => Ok(Operator::Shr),
+            "??" => Ok(Operator::Coalesce),
+            "&&" => Ok(Operator::And),
+            "||" => Ok(Operator::Or),
+            ":=" => Ok(Operator::SetVal),
+            _ => Err(format!("Unknown operator: {}", s)),
+        }
+    }
+}
+
+/// A function parameter, `type name` with an optional `:= default`. The
+/// last one can be `type name...` instead, which takes the remaining
+/// arguments as an array of `type`.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Param {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub value_type: ValueType,
+    pub default: Option<Expr>,
+    pub variadic: bool,
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.default {
+            Some(default) => write!(f, "{} {} := {}", self.value_type, self.name, default),
+            None if self.variadic => write!(f, "{} {}...", self.value_type, self.name),
+            None => write!(f, "{} {}", self.value_type, self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[allow(clippy::enum_variant_names)]
 pub enum Expr {
     Token(Token),
     UnaryExpr {
@@ -48,136 +241,1624 @@ pub enum Expr {
         name: String,
         args: Vec<Expr>,
     },
+    Const {
+        name: String,
+        expr: Box<Expr>,
+    },
+    TypedSetVal {
+        name: String,
+        value_type: ValueType,
+        expr: Box<Expr>,
+    },
+    /// `let name: type := expr`, always a new binding in the innermost
+    /// scope, unlike `:=` which assigns to a visible one if there is one.
+    Let {
+        name: String,
+        value_type: Option<ValueType>,
+        expr: Box<Expr>,
+    },
+    Break,
+    Continue,
+    FnDef {
+        name: String,
+        args: Vec<Param>,
+        return_type: ValueType,
+        body: Vec<Expr>,
+        /// The `///` comment lines right before the definition.
+        doc: Option<String>,
+    },
+    Return(Box<Expr>),
+    /// `else if` chains nest in `else_body`.
+    If {
+        cond: Box<Expr>,
+        then_body: Vec<Expr>,
+        else_body: Option<Vec<Expr>>,
+    },
+    While {
+        cond: Box<Expr>,
+        body: Vec<Expr>,
+    },
+    /// `for var in start..end`, the end is exclusive.
+    For {
+        var: String,
+        start: Box<Expr>,
+        end: Box<Expr>,
+        body: Vec<Expr>,
+    },
+    Ternary {
+        cond: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    Tuple(Vec<Expr>),
+    Array(Vec<Expr>),
+    NamedArg {
+        name: String,
+        expr: Box<Expr>,
+    },
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<(Option<Expr>, Expr)>,
+    },
+    Map(Vec<(Expr, Expr)>),
+    Block(Vec<Expr>),
+    /// The pieces of an interpolated string: literal text as string tokens
+    /// and the embedded expressions, in order.
+    Interpolation(Vec<Expr>),
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `tuple.0`
+    Field {
+        expr: Box<Expr>,
+        index: usize,
+    },
+    /// `name...` at the end of an array pattern, binding the elements the
+    /// names before it didn't take.
+    Rest(String),
+}
+
+/// Dropping a long `1 + 1 + ...` chain would recurse once per operator,
+/// take it apart on the left in a loop instead.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let Expr::BinaryExpr { lhs, .. } = self else {
+            return;
+        };
+        let mut leftmost = std::mem::replace(&mut **lhs, Expr::Break);
+        while let Expr::BinaryExpr { lhs, .. } = &mut leftmost {
+            leftmost = std::mem::replace(&mut **lhs, Expr::Break);
+        }
+    }
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            // Shown as a literal, quoted and escaped
+            Expr::Token(Token::String(s)) => write!(f, "{:?}", s),
             Expr::Token(t) => write!(f, "{}", t),
-            Expr::UnaryExpr { op, expr } => write!(f, "{} {}", op, expr),
+            Expr::UnaryExpr { op, expr } => write!(f, "{}{}", op, expr),
             Expr::BinaryExpr { op, lhs, rhs } => write!(f, "{} {} {}", lhs, op, rhs),
-            Expr::FnCall { name, args } => write!(f, "{}({:?})", name, args),
+            Expr::FnCall { name, args } => {
+                let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Const { name, expr } => write!(f, "const {} := {}", name, expr),
+            Expr::TypedSetVal {
+                name,
+                value_type,
+                expr,
+            } => write!(f, "{} {} := {}", value_type, name, expr),
+            Expr::Let {
+                name,
+                value_type: Some(value_type),
+                expr,
+            } => write!(f, "let {}: {} := {}", name, value_type, expr),
+            Expr::Let {
+                name,
+                value_type: None,
+                expr,
+            } => write!(f, "let {} := {}", name, expr),
+            Expr::Break => write!(f, "break"),
+            Expr::Continue => write!(f, "continue"),
+            Expr::FnDef {
+                name,
+                args,
+                return_type,
+                body,
+                ..
+            } => {
+                let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+                let body: Vec<_> = body.iter().map(|expr| expr.to_string()).collect();
+                write!(
+                    f,
+                    "func {}({}) -> {} {{ {} }}",
+                    name,
+                    args.join(", "),
+                    return_type,
+                    body.join("; ")
+                )
+            }
+            Expr::Return(expr) => write!(f, "return {}", expr),
+            Expr::If {
+                cond,
+                then_body,
+                else_body,
+            } => {
+                let then_body: Vec<_> = then_body.iter().map(|expr| expr.to_string()).collect();
+                write!(f, "if {} {{ {} }}", cond, then_body.join("; "))?;
+                match else_body {
+                    Some(else_body) => {
+                        let else_body: Vec<_> =
+                            else_body.iter().map(|expr| expr.to_string()).collect();
+                        write!(f, " else {{ {} }}", else_body.join("; "))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Expr::While { cond, body } => {
+                let body: Vec<_> = body.iter().map(|expr| expr.to_string()).collect();
+                write!(f, "while {} {{ {} }}", cond, body.join("; "))
+            }
+            Expr::For {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                let body: Vec<_> = body.iter().map(|expr| expr.to_string()).collect();
+                write!(
+                    f,
+                    "for {} in {}..{} {{ {} }}",
+                    var,
+                    start,
+                    end,
+                    body.join("; ")
+                )
+            }
+            Expr::Ternary {
+                cond,
+                then_expr,
+                else_expr,
+            } => write!(f, "{} ? {} : {}", cond, then_expr, else_expr),
+            Expr::Tuple(elems) => {
+                let elems: Vec<_> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Expr::Array(elems) => {
+                let elems: Vec<_> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "[{}]", elems.join(", "))
+            }
+            Expr::NamedArg { name, expr } => write!(f, "{}: {}", name, expr),
+            Expr::Match { scrutinee, arms } => {
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|(pattern, body)| match pattern {
+                        Some(pattern) => format!("{} => {}", pattern, body),
+                        None => format!("_ => {}", body),
+                    })
+                    .collect();
+                write!(f, "match {} {{ {} }}", scrutinee, arms.join(", "))
+            }
+            Expr::Map(entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{ {} }}", entries.join(", "))
+            }
+            Expr::Interpolation(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        Expr::Token(Token::String(s)) => {
+                            write!(f, "{}", s.replace('{', "{{").replace('}', "}}"))?
+                        }
+                        expr => write!(f, "{{{}}}", expr)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expr::Block(body) => {
+                let body: Vec<_> = body.iter().map(|expr| expr.to_string()).collect();
+                write!(f, "{{ {} }}", body.join("; "))
+            }
+            Expr::Index { expr, index } => write!(f, "{}[{}]", expr, index),
+            Expr::Field { expr, index } => write!(f, "{}.{}", expr, index),
+            Expr::Rest(name) => write!(f, "{}...", name),
         }
     }
 }
 
+impl Expr {
+    /// The name of the variant, e.g. `"BinaryExpr"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Expr::Token(_) => "Token",
+            Expr::UnaryExpr { .. } => "UnaryExpr",
+            Expr::BinaryExpr {
+                op: Operator::SetVal,
+                ..
+            } => "SetVal",
+            Expr::BinaryExpr { .. } => "BinaryExpr",
+            Expr::FnCall { .. } => "FnCall",
+            Expr::Const { .. } => "Const",
+            Expr::TypedSetVal { .. } => "TypedSetVal",
+            Expr::Let { .. } => "Let",
+            Expr::Break => "Break",
+            Expr::Continue => "Continue",
+            Expr::FnDef { .. } => "FnDef",
+            Expr::Return(_) => "Return",
+            Expr::If { .. } => "If",
+            Expr::While { .. } => "While",
+            Expr::For { .. } => "For",
+            Expr::Ternary { .. } => "Ternary",
+            Expr::Tuple(_) => "Tuple",
+            Expr::Array(_) => "Array",
+            Expr::NamedArg { .. } => "NamedArg",
+            Expr::Match { .. } => "Match",
+            Expr::Map(_) => "Map",
+            Expr::Block(_) => "Block",
+            Expr::Interpolation(_) => "Interpolation",
+            Expr::Index { .. } => "Index",
+            Expr::Field { .. } => "Field",
+            Expr::Rest(_) => "Rest",
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub fn to_json(exprs: &[Expr]) -> String {
+    serde_json::to_string(exprs).unwrap()
+}
+
+/// Renders `expr` as an indented tree, one node per line, for inspecting
+/// the parser's output.
+pub fn pretty(expr: &Expr, indent: usize) -> String {
+    let pad = "  ".repeat(indent);
+    let children: Vec<(String, Vec<&Expr>)>;
+    let head = match expr {
+        Expr::Token(t) => return format!("{}{:?}\n", pad, t),
+        Expr::UnaryExpr { op, expr } => {
+            children = vec![(String::new(), vec![&**expr])];
+            format!("UnaryExpr {}", op)
+        }
+        Expr::BinaryExpr { op, lhs, rhs } => {
+            children = vec![(String::new(), vec![&**lhs, &**rhs])];
+            format!("BinaryExpr {}", op)
+        }
+        Expr::FnCall { name, args } => {
+            children = vec![(String::new(), args.iter().collect())];
+            format!("FnCall {}", name)
+        }
+        Expr::Const { name, expr } => {
+            children = vec![(String::new(), vec![&**expr])];
+            format!("Const {}", name)
+        }
+        Expr::TypedSetVal {
+            name,
+            value_type,
+            expr,
+        } => {
+            children = vec![(String::new(), vec![&**expr])];
+            format!("TypedSetVal {} {}", value_type, name)
+        }
+        Expr::Let {
+            name,
+            value_type,
+            expr,
+        } => {
+            children = vec![(String::new(), vec![&**expr])];
+            match value_type {
+                Some(value_type) => format!("Let {}: {}", name, value_type),
+                None => format!("Let {}", name),
+            }
+        }
+        Expr::Break => return format!("{}Break\n", pad),
+        Expr::Rest(name) => return format!("{}Rest {}\n", pad, name),
+        Expr::Continue => return format!("{}Continue\n", pad),
+        Expr::FnDef {
+            name,
+            args,
+            return_type,
+            body,
+            ..
+        } => {
+            let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+            children = vec![("body".to_string(), body.iter().collect())];
+            format!("FnDef {}({}) -> {}", name, args.join(", "), return_type)
+        }
+        Expr::Return(expr) => {
+            children = vec![(String::new(), vec![&**expr])];
+            "Return".to_string()
+        }
+        Expr::If {
+            cond,
+            then_body,
+            else_body,
+        } => {
+            children = [
+                ("cond".to_string(), vec![&**cond]),
+                ("then".to_string(), then_body.iter().collect()),
+            ]
+            .into_iter()
+            .chain(
+                else_body
+                    .iter()
+                    .map(|body| ("else".to_string(), body.iter().collect())),
+            )
+            .collect();
+            "If".to_string()
+        }
+        Expr::While { cond, body } => {
+            children = vec![
+                ("cond".to_string(), vec![&**cond]),
+                ("body".to_string(), body.iter().collect()),
+            ];
+            "While".to_string()
+        }
+        Expr::For {
+            var,
+            start,
+            end,
+            body,
+        } => {
+            children = vec![
+                ("start".to_string(), vec![&**start]),
+                ("end".to_string(), vec![&**end]),
+                ("body".to_string(), body.iter().collect()),
+            ];
+            format!("For {}", var)
+        }
+        Expr::Ternary {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            children = vec![
+                ("cond".to_string(), vec![&**cond]),
+                ("then".to_string(), vec![&**then_expr]),
+                ("else".to_string(), vec![&**else_expr]),
+            ];
+            "Ternary".to_string()
+        }
+        Expr::Tuple(elems) => {
+            children = vec![(String::new(), elems.iter().collect())];
+            "Tuple".to_string()
+        }
+        Expr::Array(elems) => {
+            children = vec![(String::new(), elems.iter().collect())];
+            "Array".to_string()
+        }
+        Expr::NamedArg { name, expr } => {
+            children = vec![(String::new(), vec![&**expr])];
+            format!("NamedArg {}", name)
+        }
+        Expr::Match { scrutinee, arms } => {
+            children = std::iter::once(("scrutinee".to_string(), vec![&**scrutinee]))
+                .chain(arms.iter().map(|(pattern, body)| match pattern {
+                    Some(pattern) => (format!("{} =>", pattern), vec![body]),
+                    None => ("_ =>".to_string(), vec![body]),
+                }))
+                .collect();
+            "Match".to_string()
+        }
+        Expr::Map(entries) => {
+            children = entries
+                .iter()
+                .map(|(key, value)| (format!("{} =>", key), vec![value]))
+                .collect();
+            "Map".to_string()
+        }
+        Expr::Block(body) => {
+            children = vec![(String::new(), body.iter().collect())];
+            "Block".to_string()
+        }
+        Expr::Interpolation(parts) => {
+            children = vec![(String::new(), parts.iter().collect())];
+            "Interpolation".to_string()
+        }
+        Expr::Index { expr, index } => {
+            children = vec![(String::new(), vec![&**expr, &**index])];
+            "Index".to_string()
+        }
+        Expr::Field { expr, index } => {
+            children = vec![(String::new(), vec![&**expr])];
+            format!("Field {}", index)
+        }
+    };
+
+    let mut out = format!("{}{}\n", pad, head);
+    for (label, exprs) in children {
+        if label.is_empty() {
+            exprs
+                .iter()
+                .for_each(|child| out.push_str(&pretty(child, indent + 1)));
+        } else {
+            out.push_str(&format!("{}  {}\n", pad, label));
+            exprs
+                .iter()
+                .for_each(|child| out.push_str(&pretty(child, indent + 2)));
+        }
+    }
+    out
+}
+
+/// Collecting a `TokenStream` into a `Result<Parser, LexError>` stops at
+/// the first lexer error.
+impl FromIterator<Token> for Parser {
+    fn from_iter<I: IntoIterator<Item = Token>>(tokens: I) -> Self {
+        Parser::from_tokens(tokens)
+    }
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens }
+        Parser {
+            tokens,
+            spans: Vec::new(),
+            options: ParserOptions::default(),
+        }
+    }
+
+    /// Builds a parser from any source of tokens, like a `TokenStream`.
+    /// This doesn't parse as the tokens arrive: the parser can look
+    /// arbitrarily far ahead (`{` only turns out to start a map at the `:`),
+    /// so all of them are collected first and memory use is the same as
+    /// with `Parser::new`.
+    pub fn from_tokens(tokens: impl IntoIterator<Item = Token>) -> Parser {
+        Parser::new(tokens.into_iter().collect())
+    }
+
+    pub fn with_options(mut self, options: ParserOptions) -> Parser {
+        self.options = options;
+        self
     }
 
-    pub fn parse(&self) -> Vec<Expr> {
+    /// Gives the source range of each token, as from `lex_spanned`, so
+    /// errors and statements can point into the source.
+    pub fn with_spans(mut self, spans: Vec<Range<usize>>) -> Parser {
+        self.spans = spans;
+        self
+    }
+
+    /// Parses every statement, stopping at the first syntax error.
+    pub fn parse(&self) -> Result<Vec<Expr>, ParseError> {
+        let (exprs, _) = self.parse_spanned()?;
+        Ok(exprs)
+    }
+
+    /// Like `parse`, also returning the source range of each statement, or
+    /// no ranges if the parser wasn't given spans.
+    pub fn parse_spanned(&self) -> Result<(Vec<Expr>, Vec<Range<usize>>), ParseError> {
+        let (statements, origins) = self.terminate_statements();
+        // The end of the source stands in for the token after the last
+        let span_of = |index: usize| match origins.get(index) {
+            Some(&origin) => self.spans.get(origin).cloned(),
+            None => self.spans.last().map(|span| span.end..span.end),
+        };
         let mut exprs = Vec::new();
-        let mut tokens = &mut self.tokens.iter().peekable();
+        let mut spans = Vec::new();
+        let mut tokens = &mut statements.iter().peekable();
         while {
             let this = &tokens.clone();
             this.len() != 0
         } {
-            let (expr, tokens_new) = Parser::parse_expr(tokens, true);
+            let start = statements.len() - tokens.len();
+            let (expr, tokens_new) =
+                Parser::parse_expr(tokens, true).map_err(|err| ParseError {
+                    span: span_of(statements.len() - err.left),
+                    ..err
+                })?;
             tokens = tokens_new;
+            let end = statements.len() - tokens.len();
+            if let (Some(first), Some(last)) = (span_of(start), span_of(end - 1)) {
+                spans.push(first.start..last.end);
+            }
             exprs.push(expr);
         }
-        exprs
+        Ok((exprs, spans))
+    }
+
+    /// Turns the newlines that end statements into semicolons and drops the
+    /// rest, so the rest of the parser only has to deal with semicolons.
+    /// Doc comments are merged into one token in front of the function they
+    /// document, and dropped anywhere else.
+    ///
+    /// Also returns the index in `self.tokens` each token comes from.
+    fn terminate_statements(&self) -> (Vec<Token>, Vec<usize>) {
+        let mut tokens = Vec::new();
+        let mut origins = Vec::new();
+        let mut brackets = Vec::new();
+        let mut input = self.tokens.iter().enumerate().peekable();
+        while let Some((i, token)) = input.next() {
+            match token {
+                Token::DocComment(line) => {
+                    let mut lines = vec![line.as_str()];
+                    let mut ahead = input.clone();
+                    while let Some((_, token)) = ahead.next() {
+                        match token {
+                            Token::Newline => continue,
+                            Token::DocComment(line) => {
+                                lines.push(line);
+                                input = ahead.clone();
+                            }
+                            Token::Func => {
+                                tokens.push(Token::DocComment(lines.join("\n")));
+                                origins.push(i);
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                    continue;
+                }
+                Token::LParen | Token::LBracket | Token::LBrace => brackets.push(token),
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    brackets.pop();
+                }
+                Token::Newline => {
+                    if (self.options.statement_terminator == Terminator::Newline
+                        || self.options.mode == Mode::Lenient)
+                        && matches!(brackets.last(), None | Some(Token::LBrace))
+                        && matches!(
+                            tokens.last(),
+                            Some(
+                                Token::Identifier(_)
+                                    | Token::Num(_)
+                                    | Token::Float(_)
+                                    | Token::String(_)
+                                    | Token::InterpString(_)
+                                    | Token::Bool(_)
+                                    | Token::Char(_)
+                                    | Token::Break
+                                    | Token::Continue
+                                    | Token::Return
+                                    | Token::RParen
+                                    | Token::RBracket
+                                    | Token::RBrace
+                            )
+                        )
+                        && !matches!(
+                            input.peek(),
+                            Some((_, Token::RBrace | Token::Newline | Token::Else)) | None
+                        )
+                    {
+                        tokens.push(Token::Semicolon);
+                        origins.push(i);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            tokens.push(token.clone());
+            origins.push(i);
+        }
+        (tokens, origins)
     }
 
     pub fn parse_expr<'a>(
         tokens: &'a mut Peekable<Iter<'a, Token>>,
         sc_check: bool,
-    ) -> (Expr, &'a mut Peekable<Iter<'a, Token>>) {
-        let (expr, tokens_new) = match tokens.next() {
-            Some(Token::Identifier(ident)) => match tokens.next() {
-                Some(Token::SetVal) => {
-                    let (expr, tokens_new) = Parser::parse_expr(tokens, false);
-                    (
-                        Expr::BinaryExpr {
-                            op: Operator::SetVal,
+    ) -> Parsed<'a, Expr> {
+        let start = tokens.clone();
+        let (expr, tokens_new) = Parser::parse_operation(tokens)?;
+        let (expr, tokens_new) = Parser::parse_ternary(expr, tokens_new)?;
+        if sc_check {
+            // Semicolons separate statements, so the last one in a block or
+            // file doesn't need one
+            let rest = tokens_new.len();
+            match tokens_new.peek() {
+                Some(Token::Semicolon) => {
+                    tokens_new.next();
+                }
+                Some(Token::RBrace) | None => {}
+                _ if Parser::is_brace_delimited(start, rest) => {}
+                _ => return Err(ParseError::at(tokens_new, "Expected semicolon")),
+            }
+        }
+        Ok((expr, tokens_new))
+    }
+
+    /// Function definitions, matches and blocks are delimited by their
+    /// closing brace, so they don't need a semicolon. That only holds if the
+    /// brace really ends the statement: `func f() = x` and `match ... {} + 1`
+    /// still need one.
+    fn is_brace_delimited(mut start: Peekable<Iter<Token>>, rest: usize) -> bool {
+        let consumed = start.len() - rest;
+        matches!(
+            start.peek(),
+            Some(
+                Token::Func
+                    | Token::DocComment(_)
+                    | Token::Match
+                    | Token::If
+                    | Token::While
+                    | Token::For
+                    | Token::LBrace
+            )
+        ) && start.nth(consumed - 1) == Some(&Token::RBrace)
+    }
+
+    fn parse_operation<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let mut ahead = tokens.clone();
+        match (ahead.next(), ahead.next()) {
+            (Some(Token::Identifier(ident)), Some(Token::SetVal)) => {
+                tokens.next();
+                tokens.next();
+                let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                Ok((
+                    Expr::BinaryExpr {
+                        op: Operator::SetVal,
+                        lhs: Box::new(Expr::Token(Token::Identifier(ident.into()))),
+                        rhs: Box::new(expr),
+                    },
+                    tokens_new,
+                ))
+            }
+            // `x += 1` is sugar for `x := x + 1`
+            (Some(Token::Identifier(ident)), Some(Token::CompoundSet(op))) => {
+                tokens.next();
+                tokens.next();
+                let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                Ok((
+                    Expr::BinaryExpr {
+                        op: Operator::SetVal,
+                        lhs: Box::new(Expr::Token(Token::Identifier(ident.into()))),
+                        rhs: Box::new(Expr::BinaryExpr {
+                            op: op.parse().unwrap(),
                             lhs: Box::new(Expr::Token(Token::Identifier(ident.into()))),
                             rhs: Box::new(expr),
-                        },
-                        tokens_new,
-                    )
-                }
-                Some(Token::LParen) => {
-                    let (expr, tokens_new) = Parser::parse_expr(tokens, false);
-                    (
-                        Expr::FnCall {
-                            name: ident.into(),
-                            args: vec![expr],
-                        },
-                        tokens_new,
-                    )
-                }
-                _ => (Expr::Token(Token::Identifier(ident.into())), tokens),
-            },
-            Some(Token::Num(num)) => match tokens.peek() {
-                Some(Token::Semicolon) => (Expr::Token(Token::Num(*num)), tokens),
-                Some(Token::Operator(op)) => {
-                    tokens.next();
-                    let (expr, tokens_new) = Parser::parse_expr(tokens, false);
-                    match op.as_str() {
-                        "+" => (
-                            Expr::BinaryExpr {
-                                op: Operator::Add,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
-                            },
-                            tokens_new,
-                        ),
-                        "-" => (
-                            Expr::BinaryExpr {
-                                op: Operator::Sub,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
-                            },
-                            tokens_new,
-                        ),
-                        "*" => (
-                            Expr::BinaryExpr {
-                                op: Operator::Mul,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
+                        }),
+                    },
+                    tokens_new,
+                ))
+            }
+            (Some(Token::Type(value_type)), Some(Token::Identifier(ident))) => {
+                tokens.next();
+                tokens.next();
+                if tokens.next() != Some(&Token::SetVal) {
+                    return Err(ParseError::after(
+                        tokens,
+                        "Expected ':=' after typed variable",
+                    ));
+                }
+                let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                Ok((
+                    Expr::TypedSetVal {
+                        name: ident.into(),
+                        value_type: get_valuetype_from(value_type).unwrap(),
+                        expr: Box::new(expr),
+                    },
+                    tokens_new,
+                ))
+            }
+            (Some(Token::Let), _) => {
+                tokens.next();
+                let name = match tokens.next() {
+                    Some(Token::Identifier(ident)) => ident.clone(),
+                    _ => return Err(ParseError::after(tokens, "Expected identifier after let")),
+                };
+                let value_type = match tokens.next() {
+                    Some(Token::Colon) => match (tokens.next(), tokens.next()) {
+                        (Some(Token::Type(value_type)), Some(Token::SetVal)) => {
+                            Some(get_valuetype_from(value_type).unwrap())
+                        }
+                        _ => {
+                            return Err(ParseError::after(
+                                tokens,
+                                "Expected a type and ':=' after ':'",
+                            ))
+                        }
+                    },
+                    Some(Token::SetVal) => None,
+                    _ => return Err(ParseError::after(tokens, "Expected ':' or ':=' after let")),
+                };
+                let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                Ok((
+                    Expr::Let {
+                        name,
+                        value_type,
+                        expr: Box::new(expr),
+                    },
+                    tokens_new,
+                ))
+            }
+            (Some(Token::Const), _) => {
+                tokens.next();
+                match (tokens.next(), tokens.next()) {
+                    (Some(Token::Identifier(ident)), Some(Token::SetVal)) => {
+                        let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                        Ok((
+                            Expr::Const {
+                                name: ident.into(),
+                                expr: Box::new(expr),
                             },
                             tokens_new,
-                        ),
-                        "/" => (
+                        ))
+                    }
+                    _ => Err(ParseError::after(
+                        tokens,
+                        "Expected identifier and ':=' after const",
+                    )),
+                }
+            }
+            (Some(Token::Break), _) => {
+                tokens.next();
+                Ok((Expr::Break, tokens))
+            }
+            (Some(Token::Continue), _) => {
+                tokens.next();
+                Ok((Expr::Continue, tokens))
+            }
+            (Some(Token::Func), _) => {
+                tokens.next();
+                Parser::parse_fn_def(None, tokens)
+            }
+            (Some(Token::DocComment(doc)), Some(Token::Func)) => {
+                tokens.next();
+                tokens.next();
+                Parser::parse_fn_def(Some(doc.clone()), tokens)
+            }
+            (Some(Token::Return), _) => {
+                tokens.next();
+                let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+                Ok((Expr::Return(Box::new(expr)), tokens_new))
+            }
+            _ => {
+                let (expr, tokens_new) = Parser::parse_primary(tokens)?;
+                match (expr, tokens_new.peek()) {
+                    (pattern @ (Expr::Tuple(_) | Expr::Array(_)), Some(Token::SetVal)) => {
+                        tokens_new.next();
+                        Parser::check_pattern(&pattern)
+                            .map_err(|message| ParseError::after(tokens_new, message))?;
+                        let (rhs, tokens_new) = Parser::parse_expr(tokens_new, false)?;
+                        Ok((
                             Expr::BinaryExpr {
-                                op: Operator::Div,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
+                                op: Operator::SetVal,
+                                lhs: Box::new(pattern),
+                                rhs: Box::new(rhs),
                             },
                             tokens_new,
-                        ),
-                        "=" => (
+                        ))
+                    }
+                    (target @ Expr::Index { .. }, Some(Token::SetVal)) => {
+                        tokens_new.next();
+                        let (rhs, tokens_new) = Parser::parse_expr(tokens_new, false)?;
+                        Ok((
                             Expr::BinaryExpr {
-                                op: Operator::Eq,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
+                                op: Operator::SetVal,
+                                lhs: Box::new(target),
+                                rhs: Box::new(rhs),
                             },
                             tokens_new,
-                        ),
-                        "!=" => (
+                        ))
+                    }
+                    // `xs[i] += 1` is `xs[i] := xs[i] + 1`, so `i` is
+                    // evaluated twice
+                    (target @ Expr::Index { .. }, Some(Token::CompoundSet(op))) => {
+                        let op = op.parse().unwrap();
+                        tokens_new.next();
+                        let (rhs, tokens_new) = Parser::parse_expr(tokens_new, false)?;
+                        Ok((
                             Expr::BinaryExpr {
-                                op: Operator::Neq,
-                                lhs: Box::new(Expr::Token(Token::Num(*num))),
-                                rhs: Box::new(expr),
+                                op: Operator::SetVal,
+                                lhs: Box::new(target.clone()),
+                                rhs: Box::new(Expr::BinaryExpr {
+                                    op,
+                                    lhs: Box::new(target),
+                                    rhs: Box::new(rhs),
+                                }),
                             },
                             tokens_new,
-                        ),
-                        _ => todo!(),
+                        ))
+                    }
+                    (expr, _) => Parser::parse_operand(expr, tokens_new),
+                }
+            }
+        }
+    }
+
+    /// Destructuring patterns are made of names, arrays can end with a
+    /// `rest...`.
+    fn check_pattern(pattern: &Expr) -> Result<(), &'static str> {
+        let (Expr::Tuple(elems) | Expr::Array(elems)) = pattern else {
+            unreachable!()
+        };
+        for (i, elem) in elems.iter().enumerate() {
+            match elem {
+                Expr::Token(Token::Identifier(_)) => {}
+                Expr::Rest(_) if i == elems.len() - 1 && matches!(pattern, Expr::Array(_)) => {}
+                Expr::Rest(_) => {
+                    return Err("Only the last name in an array pattern can take the rest")
+                }
+                _ => return Err("Expected identifiers in destructuring pattern"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a single operand followed by any number of `[index]` and
+    /// `.0` suffixes.
+    fn parse_primary<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let (mut expr, mut tokens) = Parser::parse_atom(tokens)?;
+        loop {
+            match tokens.peek() {
+                Some(Token::LBracket) => {
+                    tokens.next();
+                    let (index, tokens_new) = Parser::parse_expr(tokens, false)?;
+                    tokens = tokens_new;
+                    if tokens.next() != Some(&Token::RBracket) {
+                        return Err(ParseError::after(tokens, "Expected ']'"));
+                    }
+                    expr = Expr::Index {
+                        expr: Box::new(expr),
+                        index: Box::new(index),
+                    };
+                }
+                Some(Token::Field(index)) => {
+                    tokens.next();
+                    expr = Expr::Field {
+                        expr: Box::new(expr),
+                        index: *index,
+                    };
+                }
+                _ => break,
+            }
+        }
+        Ok((expr, tokens))
+    }
+
+    /// Parses a literal, a variable, a function call, a map literal or a
+    /// parenthesized expression.
+    fn parse_atom<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        match tokens.next() {
+            Some(Token::Identifier(ident)) => {
+                if tokens.peek() == Some(&&Token::LParen) {
+                    tokens.next();
+                    Parser::parse_fn_call(ident, tokens)
+                } else {
+                    Ok((Expr::Token(Token::Identifier(ident.into())), tokens))
+                }
+            }
+            // Conversion builtins are named after the type they produce
+            Some(Token::Type(name)) if tokens.peek() == Some(&&Token::LParen) => {
+                tokens.next();
+                Parser::parse_fn_call(name, tokens)
+            }
+            // The lexer always emits `-` as an operator, it's unary wherever
+            // an operand is expected: `-2`, `3 - -2`, `2 * -x`. It binds
+            // tighter than everything but `**`, so `-2 ** 2` is `-(2 ** 2)`
+            Some(Token::Operator(op)) if op == "-" => {
+                let (expr, tokens) = Parser::parse_primary(tokens)?;
+                let (expr, tokens) =
+                    Parser::parse_binary(expr, Operator::Pow.precedence(), tokens)?;
+                Ok((
+                    Expr::UnaryExpr {
+                        op: Operator::Sub,
+                        expr: Box::new(expr),
+                    },
+                    tokens,
+                ))
+            }
+            Some(Token::Not) => {
+                let (expr, tokens) = Parser::parse_primary(tokens)?;
+                Ok((
+                    Expr::UnaryExpr {
+                        op: Operator::Not,
+                        expr: Box::new(expr),
+                    },
+                    tokens,
+                ))
+            }
+            Some(Token::Num(num)) => Ok((Expr::Token(Token::Num(*num)), tokens)),
+            Some(Token::Float(num)) => Ok((Expr::Token(Token::Float(*num)), tokens)),
+            Some(Token::String(s)) => Ok((Expr::Token(Token::String(s.into())), tokens)),
+            Some(Token::InterpString(s)) => match Parser::parse_interpolation(s) {
+                Ok(expr) => Ok((expr, tokens)),
+                Err(message) => Err(ParseError::after(tokens, message)),
+            },
+            Some(Token::Bool(b)) => Ok((Expr::Token(Token::Bool(*b)), tokens)),
+            Some(Token::Char(c)) => Ok((Expr::Token(Token::Char(*c)), tokens)),
+            Some(Token::LParen) => Parser::parse_group(tokens),
+            Some(Token::Match) => Parser::parse_match(tokens),
+            Some(Token::If) => Parser::parse_if(tokens),
+            Some(Token::While) => {
+                let (cond, tokens) = Parser::parse_expr(tokens, false)?;
+                if tokens.next() != Some(&Token::LBrace) {
+                    return Err(ParseError::after(tokens, "Expected '{'"));
+                }
+                let (body, tokens) = Parser::parse_block(tokens)?;
+                Ok((
+                    Expr::While {
+                        cond: Box::new(cond),
+                        body,
+                    },
+                    tokens,
+                ))
+            }
+            Some(Token::For) => Parser::parse_for(tokens),
+            // `{}` and `{ key: ...` are maps, anything else is a block
+            Some(Token::LBrace) => {
+                let mut ahead = tokens.clone();
+                match (ahead.next(), ahead.next()) {
+                    (Some(Token::RBrace), _) | (_, Some(Token::Colon)) => Parser::parse_map(tokens),
+                    _ => {
+                        let (body, tokens) = Parser::parse_block(tokens)?;
+                        Ok((Expr::Block(body), tokens))
+                    }
+                }
+            }
+            Some(Token::LBracket) => Parser::parse_array(tokens),
+            _ => Err(ParseError::after(tokens, "Expected an expression")),
+        }
+    }
+
+    /// Splits `text {expr} text` into its pieces, `{{` and `}}` stand for
+    /// literal braces. A hole can also be written `${expr}`, the `$` is
+    /// dropped. An empty `{}` is kept as is, so a quoted `format` template
+    /// only needs a raw string when it escapes braces. `s` is the literal's
+    /// raw contents: escapes are carried over as written and only replaced
+    /// once a piece of text is complete.
+    fn parse_interpolation(s: &str) -> Result<Expr, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    let rest = chars.as_str();
+                    let len = match rest.strip_prefix("u{") {
+                        Some(code) => code.find('}').map_or(1, |end| end + 3),
+                        None => rest.chars().next().map_or(0, char::len_utf8),
+                    };
+                    text.push('\\');
+                    text.push_str(&rest[..len]);
+                    chars = rest[len..].chars();
+                }
+                '$' if chars.as_str().starts_with('{') && !chars.as_str().starts_with("{{") => {}
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push_str("{}");
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or("Expected '}' in interpolated string")?;
+                    if !text.is_empty() {
+                        parts.push(Parser::interpolated_text(&std::mem::take(&mut text))?);
                     }
+                    let tokens = lex(&rest[..end]).map_err(|err| err.to_string())?;
+                    let mut tokens = tokens.iter().peekable();
+                    let (expr, tokens) =
+                        Parser::parse_expr(&mut tokens, false).map_err(|err| err.message)?;
+                    if tokens.peek().is_some() {
+                        return Err("Expected a single expression in interpolated string".into());
+                    }
+                    parts.push(expr);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err("Unmatched '}' in interpolated string, use '}}'".into()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Parser::interpolated_text(&text)?);
+        }
+        Ok(Expr::Interpolation(parts))
+    }
+
+    /// A piece of literal text between holes, with its escapes replaced.
+    fn interpolated_text(raw: &str) -> Result<Expr, String> {
+        let text = unescape_str(raw).map_err(|escape| {
+            format!("Invalid escape '{}' in interpolated string", &raw[escape])
+        })?;
+        Ok(Expr::Token(Token::String(text)))
+    }
+
+    /// Parses what follows a '[' in operand position: comma separated
+    /// elements, a trailing comma is allowed.
+    fn parse_array<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let mut tokens = tokens;
+        let mut elems = Vec::new();
+        loop {
+            if tokens.peek() == Some(&&Token::RBracket) {
+                tokens.next();
+                break;
+            }
+            let (elem, tokens_new) = Parser::parse_expr(tokens, false)?;
+            tokens = tokens_new;
+            match (&elem, tokens.peek()) {
+                (Expr::Token(Token::Identifier(name)), Some(Token::Ellipsis)) => {
+                    let name = name.clone();
+                    tokens.next();
+                    elems.push(Expr::Rest(name));
+                }
+                _ => elems.push(elem),
+            }
+            match tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RBracket) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or ']'")),
+            }
+        }
+        Ok((Expr::Array(elems), tokens))
+    }
+
+    /// Parses what follows a '{' in operand position: `key: value` pairs
+    /// separated by commas.
+    fn parse_map<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let mut tokens = tokens;
+        let mut entries = Vec::new();
+        loop {
+            if tokens.peek() == Some(&&Token::RBrace) {
+                tokens.next();
+                break;
+            }
+            let (key, tokens_new) = Parser::parse_expr(tokens, false)?;
+            if tokens_new.next() != Some(&Token::Colon) {
+                return Err(ParseError::after(tokens_new, "Expected ':' after map key"));
+            }
+            let (value, tokens_new) = Parser::parse_expr(tokens_new, false)?;
+            tokens = tokens_new;
+            entries.push((key, value));
+            match tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RBrace) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or '}'")),
+            }
+        }
+        Ok((Expr::Map(entries), tokens))
+    }
+
+    /// Parses what follows a '(': a parenthesized expression, or a tuple
+    /// if the elements are separated by commas.
+    fn parse_group<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let (first, mut tokens) = Parser::parse_expr(tokens, false)?;
+        match tokens.next() {
+            Some(Token::RParen) => return Ok((first, tokens)),
+            Some(Token::Comma) => {}
+            _ => return Err(ParseError::after(tokens, "Expected comma, or ')'")),
+        }
+        let mut elems = vec![first];
+        loop {
+            if tokens.peek() == Some(&&Token::RParen) {
+                tokens.next();
+                break;
+            }
+            let (elem, tokens_new) = Parser::parse_expr(tokens, false)?;
+            tokens = tokens_new;
+            elems.push(elem);
+            match tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or ')'")),
+            }
+        }
+        Ok((Expr::Tuple(elems), tokens))
+    }
+
+    fn parse_match<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let (scrutinee, mut tokens) = Parser::parse_expr(tokens, false)?;
+        if tokens.next() != Some(&Token::LBrace) {
+            return Err(ParseError::after(tokens, "Expected '{'"));
+        }
+        let mut arms = Vec::new();
+        loop {
+            let pattern = match tokens.next() {
+                Some(Token::RBrace) => break,
+                Some(Token::Underscore) => None,
+                Some(
+                    token @ (Token::Num(_)
+                    | Token::Float(_)
+                    | Token::String(_)
+                    | Token::Bool(_)
+                    | Token::Char(_)),
+                ) => Some(Expr::Token(token.clone())),
+                _ => {
+                    return Err(ParseError::after(
+                        tokens,
+                        "Expected a literal pattern or '_'",
+                    ))
                 }
-                _ => todo!(),
+            };
+            if tokens.next() != Some(&Token::FatArrow) {
+                return Err(ParseError::after(tokens, "Expected '=>'"));
+            }
+            let (body, tokens_new) = Parser::parse_expr(tokens, false)?;
+            tokens = tokens_new;
+            arms.push((pattern, body));
+            match tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RBrace) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or '}'")),
+            }
+        }
+        Ok((
+            Expr::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            },
+            tokens,
+        ))
+    }
+
+    /// Parses what follows an `if`: the condition, a braced body and an
+    /// optional `else`, followed by either another `if` or a braced body.
+    fn parse_if<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let (cond, tokens) = Parser::parse_expr(tokens, false)?;
+        if tokens.next() != Some(&Token::LBrace) {
+            return Err(ParseError::after(tokens, "Expected '{'"));
+        }
+        let (then_body, tokens) = Parser::parse_block(tokens)?;
+        if tokens.peek() != Some(&&Token::Else) {
+            return Ok((
+                Expr::If {
+                    cond: Box::new(cond),
+                    then_body,
+                    else_body: None,
+                },
+                tokens,
+            ));
+        }
+        tokens.next();
+        let (else_body, tokens) = match tokens.next() {
+            Some(Token::If) => {
+                let (expr, tokens) = Parser::parse_if(tokens)?;
+                (vec![expr], tokens)
+            }
+            Some(Token::LBrace) => Parser::parse_block(tokens)?,
+            _ => {
+                return Err(ParseError::after(
+                    tokens,
+                    "Expected '{' or 'if' after 'else'",
+                ))
+            }
+        };
+        Ok((
+            Expr::If {
+                cond: Box::new(cond),
+                then_body,
+                else_body: Some(else_body),
             },
-            _ => (Expr::Token(Token::Error), tokens),
+            tokens,
+        ))
+    }
+
+    /// Parses what follows a `for`: `var in start..end` and a braced body.
+    fn parse_for<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        let var = match tokens.next() {
+            Some(Token::Identifier(name)) => name.clone(),
+            _ => return Err(ParseError::after(tokens, "Expected a loop variable")),
         };
-        if sc_check {
-            if tokens_new.next() == Some(&&Token::Semicolon) {
-                (expr, tokens_new)
+        if tokens.next() != Some(&Token::In) {
+            return Err(ParseError::after(tokens, "Expected 'in'"));
+        }
+        let (start, tokens) = Parser::parse_expr(tokens, false)?;
+        if tokens.next() != Some(&Token::DotDot) {
+            return Err(ParseError::after(tokens, "Expected '..'"));
+        }
+        let (end, tokens) = Parser::parse_expr(tokens, false)?;
+        if tokens.next() != Some(&Token::LBrace) {
+            return Err(ParseError::after(tokens, "Expected '{'"));
+        }
+        let (body, tokens) = Parser::parse_block(tokens)?;
+        Ok((
+            Expr::For {
+                var,
+                start: Box::new(start),
+                end: Box::new(end),
+                body,
+            },
+            tokens,
+        ))
+    }
+
+    fn parse_ternary<'a>(
+        cond: Expr,
+        tokens: &'a mut Peekable<Iter<'a, Token>>,
+    ) -> Parsed<'a, Expr> {
+        if tokens.peek() != Some(&&Token::Question) {
+            return Ok((cond, tokens));
+        }
+        tokens.next();
+        let (then_expr, tokens) = Parser::parse_expr(tokens, false)?;
+        if tokens.next() != Some(&Token::Colon) {
+            return Err(ParseError::after(tokens, "Expected ':'"));
+        }
+        let (else_expr, tokens) = Parser::parse_expr(tokens, false)?;
+        Ok((
+            Expr::Ternary {
+                cond: Box::new(cond),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            },
+            tokens,
+        ))
+    }
+
+    fn parse_operand<'a>(lhs: Expr, tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Expr> {
+        Parser::parse_binary(lhs, 0, tokens)
+    }
+
+    /// Precedence climbing: folds operators binding at least as tightly as
+    /// `min_precedence` into `lhs`. Only a tighter (or right-associative)
+    /// operator recurses, so a flat `1 + 1 + ...` is parsed by the loop.
+    fn parse_binary<'a>(
+        lhs: Expr,
+        min_precedence: u8,
+        tokens: &'a mut Peekable<Iter<'a, Token>>,
+    ) -> Parsed<'a, Expr> {
+        let mut lhs = lhs;
+        let mut tokens = tokens;
+        while let Some(op) = Parser::peek_operator(tokens) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            tokens.next();
+            let (mut rhs, tokens_new) = Parser::parse_primary(tokens)?;
+            tokens = tokens_new;
+            while let Some(next) = Parser::peek_operator(tokens) {
+                let min_precedence = if next.precedence() > op.precedence() {
+                    op.precedence() + 1
+                } else if next.precedence() == op.precedence() && next.is_right_assoc() {
+                    op.precedence()
+                } else {
+                    break;
+                };
+                let (expr, tokens_new) = Parser::parse_binary(rhs, min_precedence, tokens)?;
+                rhs = expr;
+                tokens = tokens_new;
+            }
+            lhs = Expr::BinaryExpr {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok((lhs, tokens))
+    }
+
+    /// Every binary operator goes through here, whatever the kind of the
+    /// left operand.
+    fn peek_operator(tokens: &mut Peekable<Iter<Token>>) -> Option<Operator> {
+        match tokens.peek() {
+            Some(Token::Operator(op)) => Some(op.parse().unwrap()),
+            // A single `=` still compares too, it predates `==`
+            Some(Token::Equal) => Some(Operator::Eq),
+            Some(Token::NotEqual) => Some(Operator::Neq),
+            _ => None,
+        }
+    }
+
+    fn parse_fn_def<'a>(
+        doc: Option<String>,
+        tokens: &'a mut Peekable<Iter<'a, Token>>,
+    ) -> Parsed<'a, Expr> {
+        let name = match (tokens.next(), tokens.next()) {
+            (Some(Token::Identifier(name)), Some(Token::LParen)) => name.clone(),
+            _ => return Err(ParseError::after(tokens, "Expected function name and '('")),
+        };
+
+        let mut args: Vec<Param> = Vec::new();
+        let mut tokens = tokens;
+        let mut has_defaults = false;
+        loop {
+            if args.last().is_some_and(|arg| arg.variadic) && tokens.peek() != Some(&&Token::RParen)
+            {
+                return Err(ParseError::at(
+                    tokens,
+                    "Only the last parameter can be variadic",
+                ));
+            }
+            let arg_type = match tokens.next() {
+                Some(Token::RParen) if args.is_empty() => break,
+                Some(Token::Type(arg_type)) => get_valuetype_from(arg_type).unwrap(),
+                _ => return Err(ParseError::after(tokens, "Expected parameter type")),
+            };
+            let arg_name = match tokens.next() {
+                Some(Token::Identifier(arg_name)) => arg_name.clone(),
+                _ => return Err(ParseError::after(tokens, "Expected parameter name")),
+            };
+            let variadic = tokens.peek() == Some(&&Token::Ellipsis);
+            if variadic {
+                tokens.next();
+            }
+            let default = if tokens.peek() == Some(&&Token::SetVal) {
+                if variadic {
+                    return Err(ParseError::at(
+                        tokens,
+                        "Variadic parameters can't have a default",
+                    ));
+                }
+                tokens.next();
+                let (default, tokens_new) = Parser::parse_expr(tokens, false)?;
+                tokens = tokens_new;
+                has_defaults = true;
+                Some(default)
+            } else if has_defaults && !variadic {
+                return Err(ParseError::after(
+                    tokens,
+                    "Required parameters must come before optional ones",
+                ));
             } else {
-                panic!("Expected semicolon");
+                None
+            };
+            args.push(Param {
+                name: arg_name,
+                value_type: arg_type,
+                default,
+                variadic,
+            });
+            match tokens.next() {
+                Some(Token::Comma) if tokens.peek() == Some(&&Token::RParen) => {
+                    tokens.next();
+                    break;
+                }
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or ')'")),
+            }
+        }
+
+        let return_type = if tokens.peek() == Some(&&Token::Arrow) {
+            tokens.next();
+            match tokens.next() {
+                Some(Token::Type(return_type)) => get_valuetype_from(return_type).unwrap(),
+                _ => return Err(ParseError::after(tokens, "Expected return type")),
             }
         } else {
-            (expr, tokens_new)
+            ValueType::Nothing
+        };
+
+        // `= expr` is short for a body returning `expr`
+        let (body, tokens_new) = if tokens.peek() == Some(&&Token::Equal) {
+            tokens.next();
+            let (expr, tokens_new) = Parser::parse_expr(tokens, false)?;
+            (vec![Expr::Return(Box::new(expr))], tokens_new)
+        } else {
+            Parser::handle_func_block(tokens)?
+        };
+        Ok((
+            Expr::FnDef {
+                name,
+                args,
+                return_type,
+                body,
+                doc,
+            },
+            tokens_new,
+        ))
+    }
+
+    fn handle_func_block<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Vec<Expr>> {
+        if tokens.next() != Some(&Token::LBrace) {
+            return Err(ParseError::after(tokens, "Expected '{'"));
         }
+        Parser::parse_block(tokens)
+    }
+
+    /// Parses the statements after a '{' up to the matching '}'.
+    fn parse_block<'a>(tokens: &'a mut Peekable<Iter<'a, Token>>) -> Parsed<'a, Vec<Expr>> {
+        let mut body = Vec::new();
+        let mut tokens = tokens;
+        loop {
+            match tokens.peek() {
+                Some(Token::RBrace) => {
+                    tokens.next();
+                    break;
+                }
+                None => return Err(ParseError::at(tokens, "Expected '}'")),
+                _ => {}
+            }
+            let (expr, tokens_new) = Parser::parse_expr(tokens, true)?;
+            tokens = tokens_new;
+            body.push(expr);
+        }
+        Ok((body, tokens))
+    }
+
+    fn parse_fn_call<'a>(
+        name: &str,
+        tokens: &'a mut Peekable<Iter<'a, Token>>,
+    ) -> Parsed<'a, Expr> {
+        let mut args = Vec::new();
+        let mut tokens = tokens;
+        loop {
+            match tokens.peek() {
+                Some(Token::RParen) if args.is_empty() => {
+                    tokens.next();
+                    break;
+                }
+                Some(Token::Comma | Token::RParen) | None => {
+                    return Err(ParseError::at(tokens, "Expected argument"))
+                }
+                _ => {}
+            }
+            let mut ahead = tokens.clone();
+            let arg_name = match (ahead.next(), ahead.next()) {
+                (Some(Token::Identifier(arg_name)), Some(Token::Colon)) => {
+                    tokens.next();
+                    tokens.next();
+                    Some(arg_name.clone())
+                }
+                _ => None,
+            };
+            let (arg, tokens_new) = Parser::parse_expr(tokens, false)?;
+            tokens = tokens_new;
+            args.push(match arg_name {
+                Some(name) => Expr::NamedArg {
+                    name,
+                    expr: Box::new(arg),
+                },
+                None => arg,
+            });
+            match tokens.next() {
+                Some(Token::Comma) if tokens.peek() == Some(&&Token::RParen) => {
+                    tokens.next();
+                    break;
+                }
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                _ => return Err(ParseError::after(tokens, "Expected comma, or ')'")),
+            }
+        }
+        let named = args
+            .iter()
+            .filter(|arg| matches!(arg, Expr::NamedArg { .. }))
+            .count();
+        if named != 0 && named != args.len() {
+            return Err(ParseError::after(
+                tokens,
+                "Cannot mix positional and named arguments",
+            ));
+        }
+        Ok((
+            Expr::FnCall {
+                name: name.into(),
+                args,
+            },
+            tokens,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::lexer::{lex, lex_with_newlines, TokenStream},
+    };
+
+    fn parse(source: &str) -> Vec<Expr> {
+        Parser::new(lex(source).unwrap()).parse().unwrap()
+    }
+
+    fn parse_newlines(source: &str) -> Result<Vec<Expr>, ParseError> {
+        Parser::new(lex_with_newlines(source).unwrap())
+            .with_options(ParserOptions {
+                statement_terminator: Terminator::Newline,
+                ..ParserOptions::default()
+            })
+            .parse()
+    }
+
+    #[test]
+    fn newlines_terminate_statements_like_semicolons() {
+        let semicolons = parse(
+            "x := 1 + 2; func f(int a) -> int { b := a * 2; return b; } if x > 1 { print(f(x)); } print(x, 1);",
+        );
+        let newlines = parse_newlines(
+            "x := 1 +\n  2\nfunc f(int a) -> int {\n  b := a * 2\n  return b\n}\nif x > 1 {\n  print(f(x))\n}\nprint(\n  x,\n  1\n)\n",
+        )
+        .unwrap();
+        assert_eq!(semicolons, newlines);
+    }
+
+    #[test]
+    fn semicolons_still_work_with_newline_terminators() {
+        assert_eq!(
+            parse_newlines("x := 1; y := 2\nprint(x)").unwrap(),
+            parse("x := 1; y := 2; print(x);")
+        );
+    }
+
+    #[test]
+    fn newlines_do_not_terminate_statements_by_default() {
+        assert_eq!(parse("x := 1\n+ 2;"), parse("x := 1 + 2;"));
+    }
+
+    #[test]
+    fn pretty_prints_a_nested_function() {
+        let program = parse(
+            "func outer(int n) -> int {
+                func inner(int m) -> int { return m * 2; }
+                if n > 0 { return inner(n - 1); }
+                return 0;
+            }",
+        );
+        assert_eq!(
+            pretty(&program[0], 0),
+            "\
+FnDef outer(int n) -> int
+  body
+    FnDef inner(int m) -> int
+      body
+        Return
+          BinaryExpr *
+            Identifier(\"m\")
+            Num(2)
+    If
+      cond
+        BinaryExpr >
+          Identifier(\"n\")
+          Num(0)
+      then
+        Return
+          FnCall inner
+            BinaryExpr -
+              Identifier(\"n\")
+              Num(1)
+    Return
+      Num(0)
+"
+        );
+    }
+
+    #[test]
+    fn minus_is_unary_only_where_no_operand_precedes_it() {
+        let num = |n| Box::new(Expr::Token(Token::Num(n)));
+        let neg = |n| {
+            Box::new(Expr::UnaryExpr {
+                op: Operator::Sub,
+                expr: num(n),
+            })
+        };
+        let binary = |op, lhs, rhs| Expr::BinaryExpr { op, lhs, rhs };
+        assert_eq!(parse("3 - 2;"), [binary(Operator::Sub, num(3), num(2))]);
+        assert_eq!(parse("3 - -2;"), [binary(Operator::Sub, num(3), neg(2))]);
+        assert_eq!(parse("-3 * 2;"), [binary(Operator::Mul, neg(3), num(2))]);
+        assert_eq!(
+            parse("x := -2;"),
+            [binary(
+                Operator::SetVal,
+                Box::new(Expr::Token(Token::Identifier("x".to_string()))),
+                neg(2)
+            )]
+        );
+    }
+
+    #[test]
+    fn operators_round_trip_through_their_text() {
+        let operators = [
+            "+", "-", "*", "/", "%", "**", "==", "!=", "<", ">", "<=", ">=", "&", "|", "^", "<<",
+            ">>", "??", "&&", "||", ":=",
+        ];
+        for op in operators {
+            assert_eq!(Operator::from_str(op).unwrap().to_string(), op);
+        }
+        assert!(Operator::from_str("=").is_err());
+        // `!` is only ever a prefix, so it never goes through `from_str`
+        assert_eq!(Operator::Not.to_string(), "!");
+    }
+
+    #[test]
+    fn parses_the_same_from_a_token_stream() {
+        let source = "func f(int n) -> int { return n * 2; } x := [1, 2, 3]; print(f(x[0]) + 1);";
+        let streamed = Parser::from_tokens(TokenStream::new(source).map(Result::unwrap))
+            .parse()
+            .unwrap();
+        assert_eq!(streamed, parse(source));
     }
 }
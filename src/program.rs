@@ -0,0 +1,241 @@
+use {
+    crate::{
+        builtins::make_builtins,
+        error::Error,
+        interpreter::{run_program, Interpreter, Value},
+        lexer::{lex_spanned, Token},
+        lint::{check_loop_control, check_unreachable, Diagnostic, Severity},
+        parser::{Expr, Mode, Parser, ParserOptions},
+        resolve::resolve,
+    },
+    std::{ops::Range, slice},
+};
+
+/// A parsed and checked program, which can be run any number of times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    exprs: Vec<Expr>,
+    mode: Mode,
+    /// Lint findings that don't stop the program from running.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    /// Lexes, parses and resolves `source`.
+    pub fn compile(source: &str) -> Result<Program, Error> {
+        Program::compile_with(source, ParserOptions::default())
+    }
+
+    /// Like `compile`, but parses with `options`.
+    pub fn compile_with(source: &str, options: ParserOptions) -> Result<Program, Error> {
+        let (mut program, spans) = Program::parse(source, options)?;
+        program.warnings = check_unreachable(&program.exprs, Severity::Warning);
+        for (expr, statement) in program.exprs.iter().zip(&spans) {
+            if let Some(error) = check_loop_control(slice::from_ref(expr), Severity::Error).first()
+            {
+                return Err(Error {
+                    message: error.message.clone(),
+                    span: Some(statement.clone()),
+                });
+            }
+        }
+        if let Err(errors) = resolve(&program.exprs, make_builtins().into_keys()) {
+            let error = &errors[0];
+            let statement = spans.get(error.statement).cloned();
+            return Err(Error {
+                message: error.to_string(),
+                span: statement.map(|statement| {
+                    find_name(source, &statement, &error.name).unwrap_or(statement)
+                }),
+            });
+        }
+        Ok(program)
+    }
+
+    /// Only lexes and parses `source`, skipping the analysis passes.
+    pub fn compile_unchecked(source: &str) -> Result<Program, Error> {
+        let (program, _) = Program::parse(source, ParserOptions::default())?;
+        Ok(program)
+    }
+
+    /// Also returns the source range of each statement.
+    fn parse(source: &str, options: ParserOptions) -> Result<(Program, Vec<Range<usize>>), Error> {
+        let (tokens, spans) = lex_spanned(source)?.into_iter().unzip();
+        let mode = options.mode;
+        let (exprs, spans) = Parser::new(tokens)
+            .with_spans(spans)
+            .with_options(options)
+            .parse_spanned()?;
+        let program = Program {
+            exprs,
+            mode,
+            warnings: Vec::new(),
+        };
+        Ok((program, spans))
+    }
+
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+
+    /// Runs the program in a fresh interpreter, in the mode it was
+    /// compiled with. Use `run_with` to find out whether it called `exit`.
+    pub fn run(&self) -> Result<Value, Error> {
+        self.run_with(&mut Interpreter::new(Vec::new()).with_mode(self.mode))
+    }
+
+    /// Runs the program in `interpreter`, keeping its I/O and globals.
+    pub fn run_with(&self, interpreter: &mut Interpreter) -> Result<Value, Error> {
+        run_program(&self.exprs, &mut interpreter.state).map_err(|err| Error {
+            message: err.to_string(),
+            span: None,
+        })
+    }
+}
+
+/// Where `name` is first used as a name in the `span` of `source`.
+fn find_name(source: &str, span: &Range<usize>, name: &str) -> Option<Range<usize>> {
+    let tokens = lex_spanned(&source[span.clone()]).ok()?;
+    tokens
+        .into_iter()
+        .find(|(token, _)| matches!(token, Token::Identifier(found) if found == name))
+        .map(|(_, found)| span.start + found.start..span.start + found.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::interpreter::ValueType};
+
+    #[test]
+    fn compiles_and_runs_a_long_flat_chain() {
+        let source = format!("x := 1{}; assert_eq(x, 50000);", " + 1".repeat(49_999));
+        let program = Program::compile(&source).unwrap();
+        assert_eq!(program.run(), Ok(Value::Nothing));
+    }
+
+    #[test]
+    fn compiles_once_and_runs_twice() {
+        let program = Program::compile("x := 2; x := x * 21; assert_eq(x, 42);").unwrap();
+        assert_eq!(program.run(), Ok(Value::Nothing));
+        // Each run starts from fresh globals
+        assert_eq!(program.run(), Ok(Value::Nothing));
+    }
+
+    #[test]
+    fn runs_keep_the_interpreters_globals() {
+        let program = Program::compile_unchecked("counter := counter + 1;").unwrap();
+        let mut interpreter = Interpreter::new(Vec::new());
+        interpreter
+            .state
+            .toplevel_scope
+            .set("counter", Value::Int(0))
+            .unwrap();
+        program.run_with(&mut interpreter).unwrap();
+        program.run_with(&mut interpreter).unwrap();
+        assert_eq!(
+            interpreter.state.toplevel_scope.get("counter"),
+            Some(&Value::Int(2))
+        );
+    }
+
+    #[test]
+    fn lists_the_globals_a_program_defined() {
+        let program =
+            Program::compile("x := 1; func f() -> int { y := 2; return y; } s := \"a\";").unwrap();
+        let mut interpreter = Interpreter::new(Vec::new());
+        program.run_with(&mut interpreter).unwrap();
+        let names = interpreter.defined_names();
+        for (name, value_type) in [
+            ("x", ValueType::Int),
+            ("f", ValueType::Fn),
+            ("s", ValueType::String),
+            ("print", ValueType::Fn),
+        ] {
+            assert!(names.contains(&(name.to_string(), value_type)), "{}", name);
+        }
+        assert!(!names.iter().any(|(name, _)| name == "y"));
+    }
+
+    #[test]
+    fn a_step_limit_stops_an_infinite_loop() {
+        let program = Program::compile("i := 0; while true { i += 1; }").unwrap();
+        let mut interpreter = Interpreter::new(Vec::new()).with_max_steps(1_000);
+        let err = program.run_with(&mut interpreter).unwrap_err();
+        assert_eq!(err.message, "Step limit exceeded");
+
+        let program = Program::compile("print(1 + 2);").unwrap();
+        let mut interpreter = Interpreter::new(Vec::new())
+            .with_output(Box::new(std::io::sink()))
+            .with_max_steps(1_000);
+        assert!(program.run_with(&mut interpreter).is_ok());
+    }
+
+    #[test]
+    fn main_is_the_entry_point_when_defined() {
+        let program = Program::compile(
+            "x := 20; func helper() -> int { return 2; } func main() -> int { return x + helper(); }",
+        )
+        .unwrap();
+        assert_eq!(program.run(), Ok(Value::Int(22)));
+        let err = Program::compile("func main(int n) -> int { return n; }")
+            .unwrap()
+            .run()
+            .unwrap_err();
+        assert_eq!(err.message, "Missing argument: n");
+    }
+
+    #[test]
+    fn scripts_without_main_run_top_to_bottom() {
+        let program = Program::compile("x := 1; x := x + 1; assert_eq(x, 2);").unwrap();
+        assert_eq!(program.run(), Ok(Value::Nothing));
+    }
+
+    #[test]
+    fn lenient_mode_accepts_what_strict_mode_rejects() {
+        let lenient = ParserOptions {
+            mode: Mode::Lenient,
+            ..ParserOptions::default()
+        };
+        let message = |source: &str, options: ParserOptions| {
+            Program::compile_with(source, options)
+                .and_then(|program| program.run())
+                .err()
+                .map(|err| err.message)
+        };
+        let newlines = "x := 1\nassert_eq(x + 1, 2)";
+        assert_eq!(message(newlines, lenient.clone()), None);
+        assert_eq!(
+            message(newlines, ParserOptions::default()).as_deref(),
+            Some("Expected semicolon")
+        );
+        let coercions = r#"s := "n" + 1; assert_eq(s, "n1"); func half(float f) -> float { return f / 2; } assert_eq(half(3), 1.5);"#;
+        assert_eq!(message(coercions, lenient), None);
+        assert_eq!(
+            message(coercions, ParserOptions::default()).as_deref(),
+            Some("Invalid operands for +: string and int")
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let message = |source| Program::compile(source).unwrap_err().message;
+        assert_eq!(message("x := 1 y := 2;"), "Expected semicolon");
+        assert_eq!(
+            message("f(a: 1, 2);"),
+            "Cannot mix positional and named arguments"
+        );
+        assert_eq!(message("func f(x) {}"), "Expected parameter type");
+        assert_eq!(
+            message(r#"print("{1 2}");"#),
+            "Expected a single expression in interpolated string"
+        );
+    }
+
+    #[test]
+    fn loop_control_errors_point_at_their_statement() {
+        let source = "x := 1; if x > 0 { break; } print(x);";
+        let err = Program::compile(source).unwrap_err();
+        assert_eq!(err.message, "'break' outside of a loop");
+        assert_eq!(&source[err.span.unwrap()], "if x > 0 { break; }");
+    }
+}
@@ -0,0 +1,359 @@
+use {
+    crate::{
+        lexer::Token,
+        parser::{Expr, Operator},
+        visitor::{walk, walk_binary, Visitor},
+    },
+    std::{collections::HashSet, fmt::Display},
+};
+
+/// A name that is read without being bound anywhere it could be seen from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub name: String,
+    /// The function the read is in, `None` at the top level.
+    pub function: Option<String>,
+    /// The index of the top-level statement the read is in.
+    pub statement: usize,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(
+                f,
+                "undefined variable '{}' in function '{}'",
+                self.name, function
+            ),
+            None => write!(f, "undefined variable '{}' at the top level", self.name),
+        }
+    }
+}
+
+/// Checks every variable read in `exprs` against the names bound before it.
+/// `predefined` are names that exist before the program runs, like builtins.
+///
+/// Follows how the interpreter looks names up: the top level runs in order,
+/// so a global has to be assigned before it's read there. A function body
+/// sees its own locals and the globals, but as it can be called at any
+/// point, every global the program assigns counts as bound inside it.
+pub fn resolve(
+    exprs: &[Expr],
+    predefined: impl IntoIterator<Item = String>,
+) -> Result<(), Vec<ResolveError>> {
+    let predefined: HashSet<_> = predefined.into_iter().collect();
+    let mut assigned = GlobalAssignments(HashSet::new());
+    for expr in exprs {
+        assigned.visit_expr(expr);
+    }
+    // Functions are registered before any statement runs
+    let functions = exprs.iter().filter_map(|expr| match expr {
+        Expr::FnDef { name, .. } => Some(name.clone()),
+        _ => None,
+    });
+    // `set_global` can be called from any function before a read, so the
+    // names it binds count as bound everywhere
+    let mut set_globals = SetGlobals(HashSet::new());
+    for expr in exprs {
+        set_globals.visit_expr(expr);
+    }
+    let predefined: HashSet<_> = predefined.into_iter().chain(set_globals.0).collect();
+
+    let mut resolver = Resolver {
+        scopes: vec![predefined.iter().cloned().chain(functions).collect()],
+        function_start: 0,
+        globals: predefined.into_iter().chain(assigned.0).collect(),
+        function: None,
+        statement: 0,
+        errors: Vec::new(),
+    };
+    for (i, expr) in exprs.iter().enumerate() {
+        resolver.statement = i;
+        resolver.visit_expr(expr);
+    }
+    if resolver.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    /// The top level's names bound so far, then one set per function or
+    /// block being resolved.
+    scopes: Vec<HashSet<String>>,
+    /// Index of the innermost function's scope, 0 at the top level.
+    function_start: usize,
+    /// Every name that is global at some point of the program.
+    globals: HashSet<String>,
+    function: Option<String>,
+    /// The top-level statement being resolved.
+    statement: usize,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    fn declare(&mut self, name: &str) {
+        self.scopes.last_mut().unwrap().insert(name.to_string());
+    }
+
+    fn check(&mut self, name: &str) {
+        let visible = &self.scopes[self.function_start..];
+        let bound = visible.iter().any(|scope| scope.contains(name))
+            || (self.function_start > 0 && self.globals.contains(name));
+        if !bound {
+            self.errors.push(ResolveError {
+                name: name.to_string(),
+                function: self.function.clone(),
+                statement: self.statement,
+            });
+        }
+    }
+
+    /// Names declared in a block go out of scope at its end.
+    fn visit_block(&mut self, body: &[Expr]) {
+        self.scopes.push(HashSet::new());
+        for expr in body {
+            self.visit_expr(expr);
+        }
+        self.scopes.pop();
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FnDef {
+                name, args, body, ..
+            } => {
+                self.declare(name);
+                let outer = self.function.replace(name.clone());
+                self.scopes.push(HashSet::new());
+                let outer_start =
+                    std::mem::replace(&mut self.function_start, self.scopes.len() - 1);
+                // Defaults are evaluated in the function's scope, after the
+                // parameters before them are bound
+                for param in args {
+                    if let Some(default) = &param.default {
+                        self.visit_expr(default);
+                    }
+                    self.declare(&param.name);
+                }
+                for expr in body {
+                    self.visit_expr(expr);
+                }
+                self.scopes.pop();
+                self.function_start = outer_start;
+                self.function = outer;
+            }
+            Expr::Block(body) => self.visit_block(body),
+            Expr::If {
+                cond,
+                then_body,
+                else_body,
+            } => {
+                self.visit_expr(cond);
+                self.visit_block(then_body);
+                if let Some(else_body) = else_body {
+                    self.visit_block(else_body);
+                }
+            }
+            Expr::While { cond, body } => {
+                self.visit_expr(cond);
+                self.visit_block(body);
+            }
+            Expr::For {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                self.visit_expr(start);
+                self.visit_expr(end);
+                self.scopes.push(HashSet::from([var.clone()]));
+                for expr in body {
+                    self.visit_expr(expr);
+                }
+                self.scopes.pop();
+            }
+            Expr::Const { name, expr }
+            | Expr::TypedSetVal { name, expr, .. }
+            | Expr::Let { name, expr, .. } => {
+                self.visit_expr(expr);
+                self.declare(name);
+            }
+            expr => walk(self, expr),
+        }
+    }
+
+    fn visit_token(&mut self, token: &Token) {
+        if let Token::Identifier(name) = token {
+            self.check(name);
+        }
+    }
+
+    fn visit_binary(&mut self, op: &Operator, lhs: &Expr, rhs: &Expr) {
+        if *op != Operator::SetVal {
+            return walk_binary(self, op, lhs, rhs);
+        }
+        self.visit_expr(rhs);
+        match lhs {
+            Expr::Token(Token::Identifier(name)) => self.declare(name),
+            Expr::Tuple(_) | Expr::Array(_) => {
+                for name in pattern_names(lhs) {
+                    self.declare(name);
+                }
+            }
+            // Assigning through an index reads the container
+            lhs => self.visit_expr(lhs),
+        }
+    }
+
+    fn visit_fn_call(&mut self, name: &str, args: &[Expr]) {
+        self.check(name);
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+}
+
+/// The names a destructuring pattern binds.
+fn pattern_names(pattern: &Expr) -> Vec<&str> {
+    let (Expr::Tuple(elems) | Expr::Array(elems)) = pattern else {
+        return Vec::new();
+    };
+    elems
+        .iter()
+        .filter_map(|elem| match elem {
+            Expr::Token(Token::Identifier(name)) | Expr::Rest(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collects the names the top level assigns, without looking into function
+/// bodies, whose assignments are local.
+struct GlobalAssignments(HashSet<String>);
+
+impl Visitor for GlobalAssignments {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FnDef { name, .. } => {
+                self.0.insert(name.clone());
+            }
+            Expr::Const { name, expr }
+            | Expr::TypedSetVal { name, expr, .. }
+            | Expr::Let { name, expr, .. } => {
+                self.0.insert(name.clone());
+                self.visit_expr(expr);
+            }
+            Expr::BinaryExpr {
+                op: Operator::SetVal,
+                lhs,
+                rhs,
+            } => {
+                match &**lhs {
+                    Expr::Token(Token::Identifier(name)) => {
+                        self.0.insert(name.clone());
+                    }
+                    pattern @ (Expr::Tuple(_) | Expr::Array(_)) => {
+                        self.0
+                            .extend(pattern_names(pattern).into_iter().map(String::from));
+                    }
+                    _ => {}
+                }
+                self.visit_expr(rhs);
+            }
+            expr => walk(self, expr),
+        }
+    }
+}
+
+/// Collects the names passed to `set_global` as a string literal, anywhere
+/// in the program.
+struct SetGlobals(HashSet<String>);
+
+impl Visitor for SetGlobals {
+    fn visit_fn_call(&mut self, name: &str, args: &[Expr]) {
+        if let ("set_global", Some(Expr::Token(Token::String(global)))) = (name, args.first()) {
+            self.0.insert(global.clone());
+        }
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{lexer::lex, parser::Parser},
+    };
+
+    fn check(source: &str) -> Result<(), Vec<ResolveError>> {
+        let exprs = Parser::new(lex(source).unwrap()).parse().unwrap();
+        resolve(&exprs, ["print", "set_global"].map(String::from))
+    }
+
+    fn undefined(name: &str, function: Option<&str>, statement: usize) -> ResolveError {
+        ResolveError {
+            name: name.to_string(),
+            function: function.map(String::from),
+            statement,
+        }
+    }
+
+    #[test]
+    fn bound_names_resolve() {
+        assert_eq!(check("x := 1; print(x);"), Ok(()));
+        assert_eq!(
+            check("func f(int a) -> int { b := a; return b + y; } y := 2; print(f(1));"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reports_undefined_reads() {
+        assert_eq!(check("print(x);"), Err(vec![undefined("x", None, 0)]));
+        assert_eq!(
+            check("func f() { print(z); }"),
+            Err(vec![undefined("z", Some("f"), 0)])
+        );
+    }
+
+    #[test]
+    fn globals_must_be_assigned_before_a_top_level_read() {
+        assert_eq!(
+            check("print(x); x := 1;"),
+            Err(vec![undefined("x", None, 0)])
+        );
+    }
+
+    #[test]
+    fn block_locals_go_out_of_scope() {
+        assert_eq!(
+            check("{ let x := 1; } print(x);"),
+            Err(vec![undefined("x", None, 1)])
+        );
+    }
+
+    #[test]
+    fn resolves_a_deep_chain() {
+        let source = format!("x := 0; print(x{});", " + x".repeat(10_000));
+        assert_eq!(check(&source), Ok(()));
+    }
+
+    #[test]
+    fn set_global_binds_its_name() {
+        assert_eq!(check(r#"set_global("zz", 5); print(zz);"#), Ok(()));
+        assert_eq!(
+            check(r#"func f() { set_global("zz", 5); } f(); print(zz);"#),
+            Ok(())
+        );
+        assert_eq!(
+            check(r#"set_global("zz", 5); print(yy);"#),
+            Err(vec![undefined("yy", None, 1)])
+        );
+    }
+}
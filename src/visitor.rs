@@ -0,0 +1,265 @@
+use crate::{
+    lexer::Token,
+    parser::{Expr, Operator},
+};
+
+/// Read-only traversal of the AST. Every method has a default that just
+/// keeps walking, so an implementation only overrides what it cares about.
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk(self, expr)
+    }
+
+    fn visit_token(&mut self, _token: &Token) {}
+
+    /// Only the outermost operator of a chain like `1 + 2 + 3` comes
+    /// through here, see `walk_binary`. Override `visit_operator` to see
+    /// every operator.
+    fn visit_binary(&mut self, op: &Operator, lhs: &Expr, rhs: &Expr) {
+        walk_binary(self, op, lhs, rhs)
+    }
+
+    /// Called by `walk_binary` for each binary expression, nested ones
+    /// included, after its operands have been visited.
+    fn visit_operator(&mut self, _op: &Operator, _lhs: &Expr, _rhs: &Expr) {}
+
+    fn visit_fn_call(&mut self, _name: &str, args: &[Expr]) {
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    /// Parameter defaults have already been visited by `walk`.
+    fn visit_fn_def(&mut self, _name: &str, body: &[Expr]) {
+        for expr in body {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_return(&mut self, expr: &Expr) {
+        self.visit_expr(expr);
+    }
+}
+
+/// Hands `expr` to the matching `visit_*` method, or visits its children
+/// when there is none for its kind.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Token(token) => visitor.visit_token(token),
+        Expr::BinaryExpr { op, lhs, rhs } => visitor.visit_binary(op, lhs, rhs),
+        Expr::FnCall { name, args } => visitor.visit_fn_call(name, args),
+        Expr::FnDef {
+            name, args, body, ..
+        } => {
+            for default in args.iter().filter_map(|arg| arg.default.as_ref()) {
+                visitor.visit_expr(default);
+            }
+            visitor.visit_fn_def(name, body);
+        }
+        Expr::Return(expr) => visitor.visit_return(expr),
+        expr => {
+            for child in children(expr) {
+                visitor.visit_expr(child);
+            }
+        }
+    }
+}
+
+/// Visits the operands of a binary expression, then hands each operator to
+/// `visit_operator`. Left-associative chains nest on the left, so they're
+/// walked down in a loop, like the interpreter does, rather than going
+/// through `visit_binary` once per operator. An assignment ends the chain,
+/// as it binds a name.
+pub fn walk_binary<V: Visitor + ?Sized>(visitor: &mut V, op: &Operator, lhs: &Expr, rhs: &Expr) {
+    let mut spine = vec![(op, lhs, rhs)];
+    let mut leftmost = lhs;
+    while let Expr::BinaryExpr { op, lhs, rhs } = leftmost {
+        if *op == Operator::SetVal {
+            break;
+        }
+        spine.push((op, lhs, rhs));
+        leftmost = lhs;
+    }
+    visitor.visit_expr(leftmost);
+    for (op, lhs, rhs) in spine.into_iter().rev() {
+        visitor.visit_expr(rhs);
+        visitor.visit_operator(op, lhs, rhs);
+    }
+}
+
+/// The direct subexpressions of `expr`, in source order.
+pub fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Token(_) | Expr::Break | Expr::Continue | Expr::Rest(_) => vec![],
+        Expr::UnaryExpr { expr, .. }
+        | Expr::Const { expr, .. }
+        | Expr::TypedSetVal { expr, .. }
+        | Expr::Let { expr, .. }
+        | Expr::NamedArg { expr, .. }
+        | Expr::Field { expr, .. }
+        | Expr::Return(expr) => vec![expr],
+        Expr::BinaryExpr { lhs, rhs, .. } => vec![lhs, rhs],
+        Expr::FnCall { args, .. } => args.iter().collect(),
+        Expr::FnDef { args, body, .. } => args
+            .iter()
+            .filter_map(|arg| arg.default.as_ref())
+            .chain(body)
+            .collect(),
+        Expr::If {
+            cond,
+            then_body,
+            else_body,
+        } => std::iter::once(&**cond)
+            .chain(then_body)
+            .chain(else_body.iter().flatten())
+            .collect(),
+        Expr::While { cond, body } => std::iter::once(&**cond).chain(body).collect(),
+        Expr::For {
+            start, end, body, ..
+        } => [&**start, &**end].into_iter().chain(body).collect(),
+        Expr::Ternary {
+            cond,
+            then_expr,
+            else_expr,
+        } => vec![cond, then_expr, else_expr],
+        Expr::Tuple(elems)
+        | Expr::Array(elems)
+        | Expr::Interpolation(elems)
+        | Expr::Block(elems) => elems.iter().collect(),
+        Expr::Match { scrutinee, arms } => std::iter::once(&**scrutinee)
+            .chain(
+                arms.iter()
+                    .flat_map(|(pattern, body)| pattern.iter().chain(std::iter::once(body))),
+            )
+            .collect(),
+        Expr::Map(entries) => entries
+            .iter()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::Index { expr, index } => vec![expr, index],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{lexer::lex, parser::Parser},
+    };
+
+    #[derive(Default)]
+    struct CountTokens(usize);
+
+    impl Visitor for CountTokens {
+        fn visit_token(&mut self, _token: &Token) {
+            self.0 += 1;
+        }
+    }
+
+    fn num(n: u64) -> Expr {
+        Expr::Token(Token::Num(n))
+    }
+
+    #[test]
+    fn walks_a_deep_chain_without_recursing() {
+        let mut expr = num(1);
+        for _ in 1..10_000 {
+            expr = Expr::BinaryExpr {
+                op: Operator::Add,
+                lhs: Box::new(expr),
+                rhs: Box::new(num(1)),
+            };
+        }
+        let mut count = CountTokens::default();
+        count.visit_expr(&expr);
+        assert_eq!(count.0, 10_000);
+    }
+
+    #[test]
+    fn visits_operands_in_source_order() {
+        struct Collect(Vec<u64>);
+        impl Visitor for Collect {
+            fn visit_token(&mut self, token: &Token) {
+                if let Token::Num(n) = token {
+                    self.0.push(*n);
+                }
+            }
+        }
+        // (1 - 2) * 3 - f(4, 5)
+        let expr = Expr::BinaryExpr {
+            op: Operator::Sub,
+            lhs: Box::new(Expr::BinaryExpr {
+                op: Operator::Mul,
+                lhs: Box::new(Expr::BinaryExpr {
+                    op: Operator::Sub,
+                    lhs: Box::new(num(1)),
+                    rhs: Box::new(num(2)),
+                }),
+                rhs: Box::new(num(3)),
+            }),
+            rhs: Box::new(Expr::FnCall {
+                name: "f".to_string(),
+                args: vec![num(4), num(5)],
+            }),
+        };
+        let mut collect = Collect(Vec::new());
+        collect.visit_expr(&expr);
+        assert_eq!(collect.0, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn children_of_a_ternary() {
+        let expr = Expr::Ternary {
+            cond: Box::new(Expr::Token(Token::Bool(true))),
+            then_expr: Box::new(num(1)),
+            else_expr: Box::new(num(2)),
+        };
+        assert_eq!(
+            children(&expr),
+            [&Expr::Token(Token::Bool(true)), &num(1), &num(2)]
+        );
+        assert!(children(&num(1)).is_empty());
+    }
+
+    #[test]
+    fn counts_calls_in_a_program() {
+        #[derive(Default)]
+        struct CountCalls(Vec<String>);
+        impl Visitor for CountCalls {
+            fn visit_fn_call(&mut self, name: &str, args: &[Expr]) {
+                self.0.push(name.to_string());
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+            }
+        }
+        let program = Parser::new(
+            lex("func f(int x) -> int { return g(x) + 1; } print(f(len(\"ab\")) * 2 > 0 ? f(1) : 0);")
+                .unwrap(),
+        )
+        .parse()
+        .unwrap();
+        let mut count = CountCalls::default();
+        for expr in &program {
+            count.visit_expr(expr);
+        }
+        assert_eq!(count.0, ["g", "print", "f", "len", "f"]);
+    }
+
+    #[test]
+    fn visits_every_operator_in_a_chain() {
+        #[derive(Default)]
+        struct Operators(Vec<String>);
+        impl Visitor for Operators {
+            fn visit_operator(&mut self, op: &Operator, _lhs: &Expr, _rhs: &Expr) {
+                self.0.push(op.to_string());
+            }
+        }
+        let program = Parser::new(lex("x := 1 + 2 * 3 - 4;").unwrap())
+            .parse()
+            .unwrap();
+        let mut operators = Operators::default();
+        operators.visit_expr(&program[0]);
+        assert_eq!(operators.0, ["*", "+", "-", ":="]);
+    }
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use common::run;
+
+/// The largest int, as written in source.
+const MAX: &str = if cfg!(feature = "int32") {
+    "2147483647"
+} else {
+    "9223372036854775807"
+};
+
+fn run_with_max(source: &str) -> Result<String, String> {
+    run(&source.replace("MAX", MAX))
+}
+
+#[test]
+fn shifts() {
+    assert_eq!(
+        run("print(1 << 4, -1 << 3, 256 >> 4, -16 >> 2);"),
+        Ok("16 -8 16 -4\n".into())
+    );
+}
+
+#[test]
+#[cfg(not(feature = "int32"))]
+fn left_shift_reports_overflow() {
+    assert_eq!(run("print(1 << 63);"), Err("Integer overflow".into()));
+    assert_eq!(run("print(3 << 62);"), Err("Integer overflow".into()));
+    assert_eq!(
+        run("print(-1 << 63 == -9223372036854775807 - 1);"),
+        Ok("true\n".into())
+    );
+}
+
+#[test]
+#[cfg(feature = "int32")]
+fn left_shift_reports_overflow() {
+    assert_eq!(run("print(1 << 31);"), Err("Integer overflow".into()));
+    assert_eq!(run("print(3 << 30);"), Err("Integer overflow".into()));
+    assert_eq!(
+        run("print(-1 << 31 == -2147483647 - 1);"),
+        Ok("true\n".into())
+    );
+}
+
+#[test]
+fn arithmetic_at_the_bounds() {
+    assert_eq!(
+        run_with_max(
+            "min := -MAX - 1; print(MAX - 1 + 1 == MAX, min + 1 - 1 == min, -(min + 1) == MAX);"
+        ),
+        Ok("true true true\n".into())
+    );
+}
+
+#[test]
+fn arithmetic_reports_overflow() {
+    for source in [
+        "print(MAX + 1);",
+        "print(-MAX - 2);",
+        "print(MAX * 2);",
+        "print((MAX / 2 + 1) * -2 - 1);",
+        "print(-(-MAX - 1));",
+        "print((-MAX - 1) / -1);",
+    ] {
+        assert_eq!(
+            run_with_max(source),
+            Err("Integer overflow".into()),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn power_is_right_associative_and_binds_tighter_than_multiplication() {
+    assert_eq!(
+        run("print(2 ** 3 ** 2, (2 ** 3) ** 2, 3 * 2 ** 2, 2 ** 2 * 3);"),
+        Ok("512 64 12 12\n".into())
+    );
+    assert_eq!(
+        run("print(4.0 ** 0.5, 2 ** 0.5 == 2.0 ** 0.5);"),
+        Ok("2.0 true\n".into())
+    );
+}
+
+#[test]
+fn power_reports_overflow_and_negative_exponents() {
+    assert_eq!(run("print(2 ** 100);"), Err("Integer overflow".into()));
+    assert_eq!(
+        run("print(10 ** 10 ** 10);"),
+        Err("Integer overflow".into())
+    );
+    assert_eq!(
+        run("print(2 ** -1);"),
+        Err("Cannot raise an integer to a negative power".into())
+    );
+}
+
+#[test]
+fn arithmetic_coerces_each_operand_pairing() {
+    let operands = [("int", "6"), ("float", "6.0"), ("bool", "true")];
+    let divisors = [("int", "3"), ("float", "3.0"), ("bool", "false")];
+    for op in ["+", "-", "*", "/"] {
+        for (lhs_type, lhs) in operands {
+            for (rhs_type, rhs) in divisors {
+                let source = format!("print(typeof({} {} {}));", lhs, op, rhs);
+                let expected = match (lhs_type, rhs_type) {
+                    ("int", "int") => Ok("int\n".to_string()),
+                    ("bool", _) | (_, "bool") => Err(format!(
+                        "Invalid operands for {}: {} and {}",
+                        op, lhs_type, rhs_type
+                    )),
+                    _ => Ok("float\n".to_string()),
+                };
+                assert_eq!(run(&source), expected, "{}", source);
+            }
+        }
+    }
+}
+
+#[test]
+fn minus_works_as_both_unary_and_binary() {
+    assert_eq!(
+        run("x := -2; print(3 - 2 == 1, 3 - -2, -3 * 2, x, 3-2, -x, 2 * -x);"),
+        Ok("true 5 -6 -2 1 2 4\n".into())
+    );
+}
+
+#[test]
+fn the_int_width_decides_what_overflows() {
+    let source = "x := 65536 * 65536; print(x, x / 65536);";
+    let expected = if cfg!(feature = "int32") {
+        Err("Integer overflow".into())
+    } else {
+        Ok("4294967296 65536\n".into())
+    };
+    assert_eq!(run(source), expected);
+    assert_eq!(run("print(65535 * 32768);"), Ok("2147450880\n".into()));
+}
+
+#[test]
+fn integer_literals_in_other_bases() {
+    assert_eq!(
+        run("print(0xFF, 0b101, 0o17, 1_000, 0xff_ff + 1);"),
+        Ok("255 5 15 1000 65536\n".into())
+    );
+}
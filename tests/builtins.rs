@@ -0,0 +1,204 @@
+mod common;
+
+use common::{run, run_with_input};
+
+#[test]
+fn assert_eq_passes_on_equal_values() {
+    assert_eq!(
+        run(
+            r#"assert_eq(1, 1); assert_eq(1, 1.0); assert_eq([1, 2], [1.0, 2]); assert_eq("a", "a");"#
+        ),
+        Ok(String::new())
+    );
+}
+
+#[test]
+fn assert_eq_reports_both_sides() {
+    assert_eq!(
+        run("assert_eq(1 + 1, 3);"),
+        Err("Assertion failed: 2 != 3".into())
+    );
+    assert_eq!(
+        run(r#"assert_eq(1, "1");"#),
+        Err("Assertion failed: 1 != \"1\"".into())
+    );
+    assert_eq!(
+        run(r#"assert_eq('a', "a");"#),
+        Err("Assertion failed: 'a' != \"a\"".into())
+    );
+}
+
+#[test]
+fn int_converts_chars_and_floats() {
+    assert_eq!(
+        run("print(int('a'), int(7), int(1.5), int(-1.5), int(2.0));"),
+        Ok("97 7 1 -1 2\n".into())
+    );
+}
+
+#[test]
+fn int_rejects_other_types_and_huge_floats() {
+    assert_eq!(
+        run(r#"int("1");"#),
+        Err("Expected a value of type float, found string".into())
+    );
+    assert_eq!(run("int(1e300);"), Err("Integer overflow".into()));
+}
+
+#[test]
+fn char_and_string_conversions() {
+    assert_eq!(
+        run(r#"print(char(98), char("c"), string('d') + "!", 'a' == 'a', 'a' != 'b');"#),
+        Ok("b c d! true true\n".into())
+    );
+    assert_eq!(run("char(-1);"), Err("Cannot convert -1 to a char".into()));
+}
+
+#[test]
+fn format_substitutes_placeholders_in_order() {
+    assert_eq!(
+        run(r#"print(format("{} + {} = {}", 1, 2.5, "three"));"#),
+        Ok("1 + 2.5 = three\n".into())
+    );
+    assert_eq!(
+        run(r#"print(format("no holes"));"#),
+        Ok("no holes\n".into())
+    );
+}
+
+#[test]
+fn format_escapes_braces() {
+    assert_eq!(
+        run(r#"print(format(r"{{}} {}", 1), format(r"{{x}}"));"#),
+        Ok("{} 1 {x}\n".into())
+    );
+    assert_eq!(
+        run(r#"t := r"{{{}}}"; print(format(t, 2));"#),
+        Ok("{2}\n".into())
+    );
+}
+
+#[test]
+fn format_reports_count_mismatches() {
+    assert_eq!(
+        run(r#"format("{} {}", 1);"#),
+        Err("Format string has 2 placeholders but 1 arguments were passed".into())
+    );
+    assert_eq!(
+        run(r#"format("{}", 1, 2);"#),
+        Err("Format string has 1 placeholders but 2 arguments were passed".into())
+    );
+    assert_eq!(
+        run(r#"format(r"{", 1);"#),
+        Err("Invalid format string: unmatched '{', use '{{' for a literal one".into())
+    );
+}
+
+#[test]
+fn set_global_mutates_globals() {
+    assert_eq!(run(r#"set_global("zz", 5); print(zz);"#), Ok("5\n".into()));
+    assert_eq!(
+        run(r#"x := 1; func f() { set_global("x", x + 1); } f(); f(); print(x);"#),
+        Ok("3\n".into())
+    );
+}
+
+#[test]
+fn read_line_strips_the_line_ending() {
+    let source =
+        "print(read_line() + \"|\"); print(read_line() + \"|\"); print(read_line() + \"|\");";
+    assert_eq!(
+        run_with_input(source, "crlf\r\nlf\nlast"),
+        Ok("crlf|\nlf|\nlast|\n".into())
+    );
+    assert_eq!(
+        run_with_input("print(read_line() == \"\");", ""),
+        Ok("true\n".into())
+    );
+}
+
+#[test]
+fn typeof_names_each_type() {
+    assert_eq!(
+        run(
+            r#"func f() {} print(typeof(1), typeof(1.5), typeof("s"), typeof(true), typeof('c'), typeof(f), typeof(print), typeof(f()));"#
+        ),
+        Ok("int float string bool char fn fn nothing\n".into())
+    );
+    assert_eq!(
+        run(r#"print(typeof((1, 2)), typeof([1]), typeof({"a": 1}));"#),
+        Ok("tuple array map\n".into())
+    );
+}
+
+#[test]
+fn floor_ceil_and_round_return_ints() {
+    assert_eq!(
+        run("print(floor(1.7), floor(-1.2), ceil(1.2), ceil(-1.7), floor(3));"),
+        Ok("1 -2 2 -1 3\n".into())
+    );
+    assert_eq!(
+        run("print(round(0.5), round(1.5), round(2.5), round(-0.5), round(-1.5), round(2.4));"),
+        Ok("0 2 2 0 -2 2\n".into())
+    );
+    assert_eq!(
+        run("floor(true);"),
+        Err("Expected a value of type float, found bool".into())
+    );
+}
+
+#[test]
+fn float_widens_ints() {
+    assert_eq!(
+        run("print(float(3), float(-2), float(1.5), typeof(float(1)));"),
+        Ok("3.0 -2.0 1.5 float\n".into())
+    );
+    assert_eq!(
+        run(r#"float("1");"#),
+        Err("Expected a value of type float, found string".into())
+    );
+}
+
+#[test]
+fn len_counts_strings_arrays_and_maps() {
+    assert_eq!(
+        run(r#"print(len("héllo"), len(""), len([1, 2, 3]), len([]), len({"a": 1, "b": 2}));"#),
+        Ok("5 0 3 0 2\n".into())
+    );
+    assert_eq!(
+        run("len(5);"),
+        Err("Expected a value of type array, found int".into())
+    );
+}
+
+#[test]
+fn error_aborts_with_the_message_verbatim() {
+    assert_eq!(
+        run(r#"print(1); error("bad input: {{x}}, \"quoted\"\tend"); print(2);"#),
+        Err("bad input: {x}, \"quoted\"\tend".into())
+    );
+    assert_eq!(
+        run(r#"func check(int n) { if n < 0 { error("negative"); } } check(1); check(-1);"#),
+        Err("negative".into())
+    );
+}
+
+#[test]
+fn seeded_random_ints_are_reproducible() {
+    let draws = "[rand_int(1, 6), rand_int(1, 6), rand_int(1, 6), rand_int(1, 6)]";
+    assert_eq!(
+        run(&format!(
+            "rand_seed(42); a := {draws}; rand_seed(42); b := {draws}; print(a == b, a);",
+            draws = draws
+        )),
+        Ok("true [5, 3, 1, 6]\n".into())
+    );
+    assert_eq!(
+        run("rand_seed(7); print(rand_int(3, 3));"),
+        Ok("3\n".into())
+    );
+    assert_eq!(
+        run("rand_int(2, 1);"),
+        Err("Cannot pick a number between 2 and 1".into())
+    );
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn tuples_round_trip_through_functions() {
+    assert_eq!(
+        run(
+            r#"func swap(int a, string b) -> tuple { return (b, a); } (x, y) := swap(1, "one"); print(x, y, swap(2, "two"));"#
+        ),
+        Ok("one 1 (two, 2)\n".into())
+    );
+}
+
+#[test]
+fn tuple_fields_are_read_by_position() {
+    assert_eq!(
+        run(r#"t := (1, "two", (true, 4.5)); print(t.0, t.1, t.2.1, t.2.0 == true);"#),
+        Ok("1 two 4.5 true\n".into())
+    );
+    assert_eq!(
+        run("t := (1, 2); print(t.2);"),
+        Err("Index 2 is out of bounds for length 2".into())
+    );
+}
+
+#[test]
+fn parentheses_without_a_comma_only_group() {
+    assert_eq!(
+        run("print((1 + 2) * 3, (1, 2) == (1, 2), (1, 2) == (2, 1));"),
+        Ok("9 true false\n".into())
+    );
+}
+
+#[test]
+fn destructuring_needs_matching_lengths() {
+    assert_eq!(
+        run("(a, b) := (1, 2, 3);"),
+        Err("Cannot destructure 3 values into 2 names".into())
+    );
+}
+
+#[test]
+fn maps_can_be_built_read_and_updated() {
+    assert_eq!(
+        run(
+            r#"m := { "b": 2, "a": 1 }; print(m["a"], m["b"]); m["a"] := 10; m["c"] := 3; print(m);"#
+        ),
+        Ok("1 2\n{\"a\": 10, \"b\": 2, \"c\": 3}\n".into())
+    );
+    assert_eq!(
+        run(
+            r#"m := { "outer": { "inner": 1 } }; m["outer"]["inner"] := 2; print(m["outer"]["inner"]);"#
+        ),
+        Ok("2\n".into())
+    );
+}
+
+#[test]
+fn maps_compare_by_their_entries() {
+    assert_eq!(
+        run(
+            r#"print({ "a": 1, "b": 2 } == { "b": 2, "a": 1 }, { "a": 1 } == { "a": 2 }, {} == {});"#
+        ),
+        Ok("true false true\n".into())
+    );
+}
+
+#[test]
+fn reading_a_missing_key_is_an_error() {
+    assert_eq!(
+        run(r#"m := { "a": 1 }; print(m["b"]);"#),
+        Err("Key not found in map: \"b\"".into())
+    );
+}
+
+#[test]
+fn indices_can_be_negative_expressions() {
+    assert_eq!(
+        run(r#"a := [10, 20, 30]; i := 1; print(a[0], a[i + 1], a[-1], a[-3], "abc"[-1]);"#),
+        Ok("10 30 30 10 c\n".into())
+    );
+    assert_eq!(
+        run("a := [10, 20, 30]; a[-1] := 5; print(a);"),
+        Ok("[10, 20, 5]\n".into())
+    );
+}
+
+#[test]
+fn compound_assignment_updates_an_element() {
+    assert_eq!(
+        run("xs := [1, 2, 3]; i := 1; xs[i] += 1; xs[-1] *= 10; print(xs);"),
+        Ok("[1, 3, 30]\n".into())
+    );
+    assert_eq!(
+        run("xs := [1]; xs[1] += 1;"),
+        Err("Index 1 is out of bounds for length 1".into())
+    );
+}
+
+#[test]
+fn out_of_range_indices_are_errors() {
+    assert_eq!(
+        run("a := [10, 20, 30]; print(a[3]);"),
+        Err("Index 3 is out of bounds for length 3".into())
+    );
+    assert_eq!(
+        run("a := [10, 20, 30]; print(a[-4]);"),
+        Err("Index -4 is out of bounds for length 3".into())
+    );
+    assert_eq!(
+        run(r#"print(""[0]);"#),
+        Err("Index 0 is out of bounds for length 0".into())
+    );
+}
+
+#[test]
+fn map_and_filter_call_back_into_functions() {
+    assert_eq!(
+        run("func double(int x) -> int { return x * 2; } func even(int x) -> bool { return x % 2 == 0; } a := [1, 2, 3, 4]; print(map(a, double), filter(a, even), map(filter(a, even), double), a);"),
+        Ok("[2, 4, 6, 8] [2, 4] [4, 8] [1, 2, 3, 4]\n".into())
+    );
+    assert_eq!(run("print(map([], len));"), Ok("[]\n".into()));
+}
+
+#[test]
+fn filter_needs_a_bool_predicate() {
+    assert_eq!(
+        run("func id(int x) -> int { return x; } filter([1], id);"),
+        Err("Expected a value of type bool, found int".into())
+    );
+}
+
+#[test]
+fn push_and_pop_return_new_arrays() {
+    assert_eq!(
+        run("a := [1, 2]; b := push(a, 3); print(a, b, pop(b), push([], 1));"),
+        Ok("[1, 2] [1, 2, 3] [1, 2] [1]\n".into())
+    );
+    assert_eq!(
+        run("pop([]);"),
+        Err("Index -1 is out of bounds for length 0".into())
+    );
+}
+
+#[test]
+fn slice_clamps_its_bounds() {
+    assert_eq!(
+        run("a := [0, 1, 2, 3, 4]; print(slice(a, 1, 3), slice(a, -2, 5), slice(a, 0, 0), slice(a, -10, 10), slice(a, 4, 1), slice(a, 7, 9));"),
+        Ok("[1, 2] [3, 4] [] [0, 1, 2, 3, 4] [] []\n".into())
+    );
+    assert_eq!(
+        run("slice([1], 0, 1.5);"),
+        Err("Expected a value of type int, found float".into())
+    );
+}
+
+#[test]
+fn arrays_destructure_with_an_optional_rest() {
+    assert_eq!(
+        run("[a, b] := [1, 2]; [head, tail...] := [3, 4, 5]; [only, none...] := [6]; print(a, b, head, tail, only, none);"),
+        Ok("1 2 3 [4, 5] 6 []\n".into())
+    );
+    assert_eq!(
+        run("(x, y) := (1, \"two\"); print(x, y);"),
+        Ok("1 two\n".into())
+    );
+}
+
+#[test]
+fn array_patterns_need_enough_elements() {
+    assert_eq!(
+        run("[a, b] := [1, 2, 3];"),
+        Err("Cannot destructure 3 values into 2 names".into())
+    );
+    assert_eq!(
+        run("[a, b, rest...] := [1];"),
+        Err("Cannot destructure 1 values into 2 names and a rest".into())
+    );
+}
+
+#[test]
+fn deeply_nested_values_print_with_a_depth_cap() {
+    let expected = format!("{}...{}\n", "[".repeat(32), "]".repeat(32));
+    assert_eq!(
+        run("a := [1]; for i in 0..1000 { a := [a]; } print(a);"),
+        Ok(expected)
+    );
+    assert_eq!(
+        run(r#"print([[1, (2, {"k": [3]})]]);"#),
+        Ok("[[1, (2, {\"k\": [3]})]]\n".into())
+    );
+}
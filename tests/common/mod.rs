@@ -0,0 +1,49 @@
+#![allow(dead_code)]
+
+use {
+    pulsar::{interpreter::Interpreter, program::Program},
+    std::{
+        cell::RefCell,
+        io::{self, Cursor, Write},
+        rc::Rc,
+    },
+};
+
+/// An output sink that can still be read after the interpreter took it.
+#[derive(Clone, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compiles and runs `source`, returning what it printed, or the message
+/// of the first compile or runtime error.
+pub fn run(source: &str) -> Result<String, String> {
+    run_with_input(source, "")
+}
+
+/// Like `run`, with `input` for `read_line` to read.
+pub fn run_with_input(source: &str, input: &str) -> Result<String, String> {
+    let program = Program::compile(source).map_err(|err| err.message)?;
+    let output = Output::default();
+    let mut interpreter = Interpreter::new(Vec::new())
+        .with_input(Box::new(Cursor::new(input.to_string())))
+        .with_output(Box::new(output.clone()));
+    program
+        .run_with(&mut interpreter)
+        .map_err(|err| err.message)?;
+    Ok(output.text())
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn break_stops_the_nearest_loop() {
+    assert_eq!(
+        run("i := 0; while true { if i == 3 { break; } print(i); i += 1; }"),
+        Ok("0\n1\n2\n".into())
+    );
+    assert_eq!(
+        run("for i in 0..3 { for j in 0..10 { if j > i { break; } print(i, j); } }"),
+        Ok("0 0\n1 0\n1 1\n2 0\n2 1\n2 2\n".into())
+    );
+}
+
+#[test]
+fn continue_skips_to_the_next_iteration() {
+    assert_eq!(
+        run("for i in 0..6 { if i % 2 == 0 { continue; } print(i); }"),
+        Ok("1\n3\n5\n".into())
+    );
+    assert_eq!(
+        run("i := 0; while i < 4 { i += 1; if i == 2 { continue; } print(i); }"),
+        Ok("1\n3\n4\n".into())
+    );
+}
+
+#[test]
+fn break_outside_a_loop_is_an_error() {
+    assert_eq!(run("break;"), Err("'break' outside of a loop".into()));
+    assert_eq!(
+        run("while true { func f() { continue; } break; }"),
+        Err("'continue' outside of a loop".into())
+    );
+}
+
+#[test]
+fn ternary_picks_a_branch() {
+    assert_eq!(
+        run("flag := true; x := flag ? 1 : 2; y := !flag ? 1 : 2; print(x, y);"),
+        Ok("1 2\n".into())
+    );
+}
+
+#[test]
+fn ternary_only_evaluates_the_taken_branch() {
+    assert_eq!(
+        run(r#"print(true ? "ok" : error("untaken"), false ? 1 / 0 : 0);"#),
+        Ok("ok 0\n".into())
+    );
+}
+
+#[test]
+fn ternary_condition_must_be_a_bool() {
+    assert_eq!(
+        run("print(1 ? 2 : 3);"),
+        Err("Expected a value of type bool, found int".into())
+    );
+}
+
+#[test]
+fn match_runs_the_first_equal_arm() {
+    let source = "func name(int x) -> string { return match x { 1 => \"one\", 2 => \"two\", 1 => \"again\", _ => \"many\" }; } ";
+    assert_eq!(
+        run(&format!("{}print(name(1), name(2), name(7));", source)),
+        Ok("one two many\n".into())
+    );
+    assert_eq!(
+        run("print(match 2.0 { 1 => \"one\", 2 => \"two\" });"),
+        Ok("two\n".into())
+    );
+}
+
+#[test]
+fn match_without_a_wildcard_can_match_nothing() {
+    assert_eq!(
+        run("x := match 3 { 1 => 10, 2 => 20 }; print(x);"),
+        Ok("nothing\n".into())
+    );
+}
+
+#[test]
+fn match_compares_like_equality() {
+    assert_eq!(
+        run("print(match \"b\" { \"a\" => 1, \"b\" => 2, _ => 3 }, match 'c' { 'c' => 1, _ => 2 });"),
+        Ok("2 1\n".into())
+    );
+    assert_eq!(
+        run("print(match 1 { \"1\" => \"string\", _ => \"other\" });"),
+        Err("Invalid operands for ==: int and string".into())
+    );
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use {
+    common::Output,
+    pulsar::{
+        interpreter::{Interpreter, RuntimeError, Value},
+        lexer::lex,
+        parser::{Expr, Parser},
+        program::Program,
+    },
+    std::time::Duration,
+};
+
+fn expr(source: &str) -> Expr {
+    Parser::new(lex(source).unwrap()).parse().unwrap().remove(0)
+}
+
+#[test]
+fn expressions_share_the_interpreters_globals() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    assert_eq!(
+        interpreter.eval_expr(&expr("1 + 2 * 3;")),
+        Ok(Value::Int(7))
+    );
+    assert_eq!(interpreter.eval_expr(&expr("x := 40;")), Ok(Value::Int(40)));
+    interpreter
+        .eval_expr(&expr("func add(int n) -> int { return x + n; }"))
+        .unwrap();
+    assert_eq!(interpreter.eval_expr(&expr("add(2);")), Ok(Value::Int(42)));
+    assert_eq!(
+        interpreter.eval_expr(&expr("y;")),
+        Err(RuntimeError::UndefinedVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn clock_reads_the_injected_time_source() {
+    let mut now = Duration::from_secs(10);
+    let mut interpreter = Interpreter::new(Vec::new()).with_clock(move || {
+        now += Duration::from_millis(250);
+        now
+    });
+    let program = Program::compile(
+        "a := clock(); b := clock(); assert_eq(b - a, 0.25); assert_eq(a, 10.25);",
+    )
+    .unwrap();
+    assert_eq!(program.run_with(&mut interpreter), Ok(Value::Nothing));
+}
+
+#[test]
+fn documented_functions_expose_their_doc_comment() {
+    let program = Program::compile(
+        "/// Adds one.\n/// Never fails.\nfunc inc(int n) -> int { return n + 1; }\n// Just a comment.\nfunc dec(int n) -> int { return n - 1; }",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::new(Vec::new());
+    program.run_with(&mut interpreter).unwrap();
+    assert_eq!(
+        interpreter.function_doc("inc"),
+        Some("Adds one.\nNever fails.")
+    );
+    assert_eq!(interpreter.function_doc("dec"), None);
+    assert_eq!(interpreter.function_doc("missing"), None);
+}
+
+#[test]
+fn exit_unwinds_loops_and_calls_and_reports_its_code() {
+    let output = Output::default();
+    let program = Program::compile(
+        "func stop(int n) { for i in 0..10 { while true { if i == n { exit(3); } break; } print(i); } } stop(2); print(\"after\");",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::new(Vec::new()).with_output(Box::new(output.clone()));
+    assert_eq!(program.run_with(&mut interpreter), Ok(Value::Nothing));
+    assert_eq!(interpreter.exit_code(), Some(3));
+    assert_eq!(output.text(), "0\n1\n");
+
+    let mut interpreter = Interpreter::new(Vec::new());
+    Program::compile("x := 1;")
+        .unwrap()
+        .run_with(&mut interpreter)
+        .unwrap();
+    assert_eq!(interpreter.exit_code(), None);
+}
+
+#[test]
+fn an_evaluated_exit_sets_the_exit_code() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    assert_eq!(interpreter.eval_expr(&expr("x := 2;")), Ok(Value::Int(2)));
+    assert_eq!(interpreter.exit_code(), None);
+    assert_eq!(
+        interpreter.eval_expr(&expr("exit(x + 1);")),
+        Ok(Value::Nothing)
+    );
+    assert_eq!(interpreter.exit_code(), Some(3));
+}
+
+#[test]
+fn a_trace_lists_each_evaluation_and_counts_kinds() {
+    let trace = Output::default();
+    let mut interpreter = Interpreter::new(Vec::new()).with_trace(Box::new(trace.clone()));
+    Program::compile("x := 1 + 2 + 3;")
+        .unwrap()
+        .run_with(&mut interpreter)
+        .unwrap();
+    assert_eq!(
+        trace.text(),
+        "Token => 1\nToken => 2\nBinaryExpr => 3\nToken => 3\nBinaryExpr => 6\nSetVal => 6\n"
+    );
+    assert_eq!(
+        interpreter.trace_counts(),
+        [("Token", 3), ("BinaryExpr", 2), ("SetVal", 1)]
+    );
+
+    let mut interpreter = Interpreter::new(Vec::new());
+    Program::compile("x := 1 + 2;")
+        .unwrap()
+        .run_with(&mut interpreter)
+        .unwrap();
+    assert!(interpreter.trace_counts().is_empty());
+}
+
+#[test]
+fn functions_are_defined_once() {
+    let trace = Output::default();
+    let mut interpreter = Interpreter::new(Vec::new()).with_trace(Box::new(trace.clone()));
+    Program::compile("func f() -> int { return 1; } x := f();")
+        .unwrap()
+        .run_with(&mut interpreter)
+        .unwrap();
+    assert_eq!(trace.text().matches("FnDef").count(), 1);
+    assert!(interpreter.trace_counts().contains(&("FnDef", 1)));
+}
+
+#[test]
+fn a_builder_from_elsewhere_is_an_error() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    interpreter
+        .state
+        .toplevel_scope
+        .set("b", Value::Builder(99))
+        .unwrap();
+    assert_eq!(
+        interpreter.eval_expr(&expr("sb_push(b, \"x\");")),
+        Err(RuntimeError::UnknownBuilder(99))
+    );
+    assert_eq!(
+        interpreter.eval_expr(&expr("sb_build(b);")),
+        Err(RuntimeError::UnknownBuilder(99))
+    );
+}
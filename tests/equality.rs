@@ -0,0 +1,50 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn numbers_compare_by_value_across_types() {
+    assert_eq!(run("print(1 == 1.0, 2 != 2.0);"), Ok("true false\n".into()));
+}
+
+#[test]
+fn containers_compare_elements_by_value() {
+    assert_eq!(
+        run("print([1] == [1.0], (1, [2]) == (1.0, [2.0]));"),
+        Ok("true true\n".into())
+    );
+    assert_eq!(
+        run(r#"print({"a": 1} == {"a": 1.0}, [1, 2] != [1.0, 2.5]);"#),
+        Ok("true true\n".into())
+    );
+}
+
+#[test]
+fn containers_of_different_shapes_are_unequal() {
+    assert_eq!(
+        run(r#"print([1] == [1, 1], [1] == ["1"], {"a": 1} == {"b": 1});"#),
+        Ok("false false false\n".into())
+    );
+}
+
+#[test]
+fn nested_containers_compare_recursively() {
+    assert_eq!(
+        run(
+            r#"print([[1, 2], [3]] == [[1, 2], [3]], [[1, 2], [3]] == [[1, 2], [4]], ([1], {"k": (2, 3)}) == ([1], {"k": (2, 3)}));"#
+        ),
+        Ok("true false true\n".into())
+    );
+    assert_eq!(
+        run("print([] == [], [] == [1]);"),
+        Ok("true false\n".into())
+    );
+}
+
+#[test]
+fn arrays_and_tuples_cannot_be_compared() {
+    assert_eq!(
+        run("print([1] == (1, 2));"),
+        Err("Invalid operands for ==: array and tuple".into())
+    );
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn trailing_commas_in_definitions_and_calls() {
+    assert_eq!(
+        run("func f(int a, int b,) -> int { return a - b; } print(f(5, 3,), f(5, 3));"),
+        Ok("2 2\n".into())
+    );
+    assert_eq!(
+        run("func g() -> int { return 1; } print(g());"),
+        Ok("1\n".into())
+    );
+}
+
+#[test]
+fn a_lone_comma_is_an_error() {
+    assert_eq!(
+        run("func g() -> int { return 1; } g(,);"),
+        Err("Expected argument".into())
+    );
+    assert_eq!(run("func g(,) {}"), Err("Expected parameter type".into()));
+}
+
+#[test]
+fn named_arguments_bind_by_name_in_any_order() {
+    assert_eq!(
+        run("func f(int a, int b) -> int { return a - b; } print(f(b: 1, a: 5), f(a: 5, b: 1));"),
+        Ok("4 4\n".into())
+    );
+}
+
+#[test]
+fn defaults_see_the_parameters_before_them() {
+    assert_eq!(
+        run("func f(int a, int b := a * 2) -> int { return a + b; } print(f(1), f(1, 1), f(b: 0, a: 3));"),
+        Ok("3 2 3\n".into())
+    );
+}
+
+#[test]
+fn named_arguments_reject_bad_names() {
+    let f = "func f(int a, int b) -> int { return a - b; } ";
+    assert_eq!(
+        run(&format!("{}f(a: 1, c: 2);", f)),
+        Err("Unknown argument: c".into())
+    );
+    assert_eq!(
+        run(&format!("{}f(a: 1, a: 2);", f)),
+        Err("Argument passed more than once: a".into())
+    );
+    assert_eq!(
+        run(&format!("{}f(b: 1);", f)),
+        Err("Missing argument: a".into())
+    );
+}
+
+#[test]
+fn named_and_positional_arguments_do_not_mix() {
+    assert_eq!(
+        run("func f(int a, int b) -> int { return a - b; } f(1, b: 2);"),
+        Err("Cannot mix positional and named arguments".into())
+    );
+}
+
+#[test]
+fn omitted_arguments_take_their_defaults() {
+    assert_eq!(
+        run("func f(int x, int y := 10) -> int { return x + y; } print(f(5), f(5, 1));"),
+        Ok("15 6\n".into())
+    );
+    assert_eq!(
+        run("func f(int x, int y := 10) -> int { return x + y; } f();"),
+        Err("Missing argument: x".into())
+    );
+}
+
+#[test]
+fn required_parameters_come_first() {
+    assert_eq!(
+        run("func f(int x := 1, int y) -> int { return x + y; }"),
+        Err("Required parameters must come before optional ones".into())
+    );
+}
+
+#[test]
+fn calls_compose_inside_expressions() {
+    assert_eq!(
+        run("func f(int x) -> int { return x * 10; } print(1 + f(2), f(1) * f(2) - 1, f(f(1)) / 4, -f(3));"),
+        Ok("21 199 25 -30\n".into())
+    );
+    assert_eq!(
+        run(r#"s := "abc"; print(len(s) + 1, string(len(s)) + "!");"#),
+        Ok("4 3!\n".into())
+    );
+}
+
+#[test]
+fn functions_can_call_ones_defined_later() {
+    assert_eq!(
+        run("func is_even(int n) -> bool { return n == 0 ? true : is_odd(n - 1); } func is_odd(int n) -> bool { return n == 0 ? false : is_even(n - 1); } print(is_even(10), is_odd(7), is_even(3));"),
+        Ok("true true false\n".into())
+    );
+    assert_eq!(
+        run("print(later()); func later() -> int { return 1; }"),
+        Ok("1\n".into())
+    );
+}
+
+#[test]
+fn functions_print_their_signature() {
+    assert_eq!(
+        run("func add(int a, float b := 1.0) -> float { return a + b; } print(add); print(len);"),
+        Ok("func add(int a, float b := 1.0) -> float\nbuiltin len\n".into())
+    );
+}
+
+#[test]
+fn expression_bodies_return_their_value() {
+    assert_eq!(
+        run("func square(int x) -> int = x * x; print(square(4), square(square(2)));"),
+        Ok("16 16\n".into())
+    );
+    assert_eq!(
+        run(r#"func bad(int x) -> int = "no"; bad(1);"#),
+        Err("Expected a value of type int, found string".into())
+    );
+}
+
+#[test]
+fn a_body_evaluates_to_its_last_expression() {
+    assert_eq!(
+        run("func abs(int n) -> int { if n < 0 { -n } else { n } } x := if abs(-3) > 2 { \"big\" } else { \"small\" }; print(abs(-3), abs(4), x);"),
+        Ok("3 4 big\n".into())
+    );
+}
+
+#[test]
+fn parameters_bind_in_declaration_order() {
+    let source = r#"func f(string c, int a, float b) -> string { return format("{} {} {}", c, a, b); } print(f("x", 1, 2.5)); print(f);"#;
+    for _ in 0..5 {
+        assert_eq!(
+            run(source),
+            Ok("x 1 2.5\nfunc f(string c, int a, float b) -> string\n".into())
+        );
+    }
+}
+
+#[test]
+fn overloads_are_picked_by_arity() {
+    let source =
+        "func f(int x) -> int { return x; } func f(int x, int y) -> int { return x + y; } ";
+    assert_eq!(
+        run(&format!("{}print(f(1), f(1, 2));", source)),
+        Ok("1 3\n".into())
+    );
+    assert_eq!(
+        run(&format!("{}f(1, 2, 3);", source)),
+        Err("No overload of f takes 3 arguments, it takes 1 or 2".into())
+    );
+}
+
+#[test]
+fn variadic_parameters_collect_the_rest() {
+    let sum = "func sum(int first, int nums...) -> int { total := first; for i in 0..len(nums) { total += nums[i]; } return total; } ";
+    assert_eq!(
+        run(&format!(
+            "{}print(sum(1), sum(1, 2), sum(1, 2, 3, 4));",
+            sum
+        )),
+        Ok("1 3 10\n".into())
+    );
+    assert_eq!(
+        run("func rest(int nums...) -> array { return nums; } print(rest(), rest(1, 2));"),
+        Ok("[] [1, 2]\n".into())
+    );
+}
+
+#[test]
+fn only_the_last_parameter_can_be_variadic() {
+    assert_eq!(
+        run("func f(int a..., int b) {}"),
+        Err("Only the last parameter can be variadic".into())
+    );
+    assert_eq!(
+        run("func f(int a..., int b...) {}"),
+        Err("Only the last parameter can be variadic".into())
+    );
+    assert_eq!(
+        run("func f(int a... := 1) {}"),
+        Err("Variadic parameters can't have a default".into())
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn every_operator_accepts_identifier_and_literal_operands() {
+    let cases = [
+        ("+", "9 9 9 9"),
+        ("-", "5 5 5 5"),
+        ("*", "14 14 14 14"),
+        ("/", "3 3 3 3"),
+        ("%", "1 1 1 1"),
+        ("==", "false false false false"),
+        ("!=", "true true true true"),
+        ("<", "false false false false"),
+        (">", "true true true true"),
+        ("<=", "false false false false"),
+        (">=", "true true true true"),
+    ];
+    for (op, expected) in cases {
+        let source = format!(
+            "a := 7; b := 2; print(a {op} b, a {op} 2, 7 {op} b, 7 {op} 2);",
+            op = op
+        );
+        assert_eq!(run(&source), Ok(format!("{}\n", expected)), "{}", op);
+    }
+}
+
+#[test]
+fn logical_operators_accept_identifiers_and_literals() {
+    assert_eq!(
+        run("t := true; f := false; print(t && false, true || f, f && t, t || true);"),
+        Ok("false true false true\n".into())
+    );
+}
+
+#[test]
+fn coalescing_falls_back_only_on_nothing() {
+    assert_eq!(
+        run("func none() {} print(none() ?? 2, 1 ?? 2, false ?? true, none() ?? none() ?? 3);"),
+        Ok("2 1 false 3\n".into())
+    );
+}
+
+#[test]
+fn coalescing_skips_the_fallback_when_a_value_is_present() {
+    assert_eq!(
+        run(r#"func loud() -> int { print("evaluated"); return 2; } print(1 ?? loud());"#),
+        Ok("1\n".into())
+    );
+    assert_eq!(
+        run(
+            r#"func none() {} func loud() -> int { print("evaluated"); return 2; } print(none() ?? loud());"#
+        ),
+        Ok("evaluated\n2\n".into())
+    );
+}
+
+#[test]
+fn strings_order_lexicographically() {
+    assert_eq!(
+        run(r#"print("apple" < "banana", "b" > "abc", "abc" <= "abc", "Z" >= "a", "" < "a");"#),
+        Ok("true true true false true\n".into())
+    );
+}
+
+#[test]
+fn strings_and_ints_cannot_be_ordered() {
+    assert_eq!(
+        run(r#"print("1" < 2);"#),
+        Err("Invalid operands for <: string and int".into())
+    );
+}
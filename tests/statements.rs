@@ -0,0 +1,62 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn the_last_statement_in_a_file_needs_no_semicolon() {
+    assert_eq!(run("x := 1; print(x)"), Ok("1\n".into()));
+    assert_eq!(run("print(1);"), Ok("1\n".into()));
+}
+
+#[test]
+fn the_last_statement_in_a_block_needs_no_semicolon() {
+    assert_eq!(
+        run("func f() -> int { return 1 } if true { print(f()) }"),
+        Ok("1\n".into())
+    );
+}
+
+#[test]
+fn inner_statements_still_need_separators() {
+    assert_eq!(run("print(1) print(2)"), Err("Expected semicolon".into()));
+    assert_eq!(
+        run("if true { print(1) print(2) }"),
+        Err("Expected semicolon".into())
+    );
+}
+
+#[test]
+fn newlines_between_tokens_are_insignificant() {
+    assert_eq!(
+        run("x :=\n 1 +\n 2;\ny := 2\n  * (3\n  - 1)\n;\nprint(\n  x,\n  y\n);"),
+        Ok("3 4\n".into())
+    );
+    assert_eq!(
+        run("print(1 +\n2 ==\n3 &&\ntrue);"),
+        run("print(1 + 2 == 3 && true);")
+    );
+}
+
+#[test]
+fn function_definitions_end_at_their_closing_brace() {
+    assert_eq!(
+        run("func f() -> int { return 1; } func g() -> int { return 2; } print(f(), g());"),
+        Ok("1 2\n".into())
+    );
+    assert_eq!(
+        run("func f() -> int { return 1; } x := f(); print(x);"),
+        Ok("1\n".into())
+    );
+    assert_eq!(
+        run("func f() -> int { return 1; }; print(f());"),
+        Ok("1\n".into())
+    );
+}
+
+#[test]
+fn comments_are_skipped() {
+    assert_eq!(
+        run("x := 1; // the rest of the line\ny := /* between tokens */ 2;\n/* several\n   lines */ print(x + y);"),
+        Ok("3\n".into())
+    );
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn char_literals_and_escapes() {
+    assert_eq!(
+        run(r"print('a', '\n' == char(10), '\'' == char(39));"),
+        Ok("a true true\n".into())
+    );
+}
+
+#[test]
+fn unicode_escapes_stand_for_their_character() {
+    assert_eq!(
+        run(
+            r#"x := 1; print("\u{41}\u{e9}\u{1F600}", '\u{7A}', "\u{7B}", "a\u{7B}b\u{7D}", "{x}\u{7D}\n");"#
+        ),
+        Ok("Aé😀 z { a{b} 1}\n\n".into())
+    );
+    assert_eq!(
+        run(r#"x := 1; print("\"{x}\" \\{x}\t.");"#),
+        Ok("\"1\" \\1\t.\n".into())
+    );
+}
+
+#[test]
+fn invalid_escapes_are_errors() {
+    for (source, escape) in [
+        (r#"print("\q");"#, r"\q"),
+        (r#"print("\u{}");"#, r"\u{}"),
+        (r#"print("\u{110000}");"#, r"\u{110000}"),
+        (r#"print("\u{D800}");"#, r"\u{D800}"),
+        (r#"print("\u{1234567}");"#, r"\u{1234567}"),
+        (r#"print("\u{4G}");"#, r"\u{4G}"),
+        (r#"print("\u41");"#, r"\u"),
+        (r#"x := 1; print("{x} \q");"#, r"\q"),
+        (r"print('\q');", r"\q"),
+    ] {
+        assert_eq!(
+            run(source),
+            Err(format!("invalid escape '{}'", escape)),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn chars_concatenate_with_strings() {
+    assert_eq!(
+        run(r#"s := "ab"; print(s + 'c', 'x' + s, 'x' + 'y', char_at(s, 1) == 'b');"#),
+        Ok("abc xab xy true\n".into())
+    );
+}
+
+#[test]
+fn multiline_strings_keep_their_newlines() {
+    assert_eq!(
+        run("s := \"\"\"one\n  two \"quoted\"\nthree\"\"\"; print(s);"),
+        Ok("one\n  two \"quoted\"\nthree\n".into())
+    );
+}
+
+#[test]
+fn raw_strings_keep_their_backslashes() {
+    assert_eq!(
+        run(r#"print(r"C:\new\table", "a\tb");"#),
+        Ok("C:\\new\\table a\tb\n".into())
+    );
+}
+
+#[test]
+fn strings_interpolate_expressions() {
+    assert_eq!(
+        run(r#"x := 3; a := 1; b := 2; print("x is {x}, sum is {a + b}");"#),
+        Ok("x is 3, sum is 3\n".into())
+    );
+    assert_eq!(
+        run(r#"func f(int n) -> int { return n * 2; } print("{f(2)}{f(3) + 1}!");"#),
+        Ok("47!\n".into())
+    );
+}
+
+#[test]
+fn holes_can_start_with_a_dollar_sign() {
+    assert_eq!(
+        run(r#"x := 2; print("${x} and {x * 2}, ${{x}}");"#),
+        Ok("2 and 4, ${x}\n".into())
+    );
+}
+
+#[test]
+fn doubled_braces_are_literal() {
+    assert_eq!(
+        run(r#"x := 1; print("{{x}} = {x}", "}}{{");"#),
+        Ok("{x} = 1 }{\n".into())
+    );
+}
+
+#[test]
+fn arguments_to_a_function_named_format_are_interpolated() {
+    assert_eq!(
+        run(
+            r#"func format(string s) -> string { return s; } assert_eq(format("{1}"), "1"); print(format("{{x}}"));"#
+        ),
+        Ok("{x}\n".into())
+    );
+}
+
+#[test]
+fn case_conversion_handles_unicode() {
+    assert_eq!(
+        run(r#"print(upper("straße é"), lower("ÀÉÎ Ω"), "[" + trim("  \t mid dle \n") + "]");"#),
+        Ok("STRASSE É àéî ω [mid dle]\n".into())
+    );
+    assert_eq!(
+        run("upper(1);"),
+        Err("Expected a value of type string, found int".into())
+    );
+}
+
+#[test]
+fn a_string_builder_matches_concatenation() {
+    assert_eq!(
+        run(
+            r#"sb := sb_new(); s := ""; for i in 0..2000 { sb_push(sb, string(i)); sb_push(sb, ','); s := s + string(i) + ","; } built := sb_build(sb); print(built == s, len(built));"#
+        ),
+        Ok("true 8890\n".into())
+    );
+    assert_eq!(
+        run(
+            r#"sb := sb_new(); sb_push(sb, "a"); print(sb_build(sb)); sb_push(sb, "b"); print(sb_build(sb));"#
+        ),
+        Ok("a\nab\n".into())
+    );
+}
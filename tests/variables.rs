@@ -0,0 +1,95 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn variables_can_be_reassigned() {
+    assert_eq!(run("x := 1; x := x + 1; print(x);"), Ok("2\n".into()));
+}
+
+#[test]
+fn consts_cannot_be_reassigned() {
+    assert_eq!(run("const x := 1; print(x);"), Ok("1\n".into()));
+    assert_eq!(
+        run("const x := 1; x := 2;"),
+        Err("Cannot assign to constant: x".into())
+    );
+    assert_eq!(
+        run("const x := 1; func f() { x := 2; } f(); print(x);"),
+        Ok("1\n".into())
+    );
+}
+
+#[test]
+fn typed_declarations_check_their_values() {
+    assert_eq!(
+        run("int x := 5; float y := 1.5; string s := \"hi\"; print(x, y, s);"),
+        Ok("5 1.5 hi\n".into())
+    );
+    assert_eq!(
+        run("int x := \"hi\";"),
+        Err("Expected a value of type int, found string".into())
+    );
+}
+
+#[test]
+fn typed_variables_keep_their_type_on_reassignment() {
+    assert_eq!(run("int x := 5; x := 6; print(x);"), Ok("6\n".into()));
+    assert_eq!(
+        run("int x := 5; x := true;"),
+        Err("Expected a value of type int, found bool".into())
+    );
+}
+
+#[test]
+fn let_declares_a_new_binding_in_the_current_scope() {
+    assert_eq!(
+        run("x := 1; { let x: int := 2; x := x + 1; print(x); } let y := 5; print(x, y);"),
+        Ok("3\n1 5\n".into())
+    );
+    assert_eq!(
+        run("let x: int := true;"),
+        Err("Expected a value of type int, found bool".into())
+    );
+    assert_eq!(
+        run(r#"let x: int := 1; x := "s";"#),
+        Err("Expected a value of type int, found string".into())
+    );
+}
+
+#[test]
+fn blocks_yield_their_last_expression() {
+    assert_eq!(run("x := { a := 1; a + 2 }; print(x);"), Ok("3\n".into()));
+    assert_eq!(
+        run("x := { a := 1; a + 2 }; print(a);"),
+        Err("undefined variable 'a' at the top level".into())
+    );
+}
+
+#[test]
+fn every_typeof_name_works_as_an_annotation() {
+    let source = r#"func f() {} sb := sb_new(); values := [1, 1.5, "s", true, 'c', f, (1, 2), [1], {"a": 1}, sb, f()]; for i in 0..len(values) { print(typeof(values[i])); }"#;
+    let names = run(source).unwrap();
+    let values = [
+        "1",
+        "1.5",
+        "\"s\"",
+        "true",
+        "'c'",
+        "f",
+        "(1, 2)",
+        "[1]",
+        "{\"a\": 1}",
+        "sb",
+        "f()",
+    ];
+    for (name, value) in names.lines().zip(values) {
+        let source = format!(
+            "func f() {{}} sb := sb_new(); {name} x := {value}; let y: {name} := {value}; print(typeof(x) == \"{name}\", typeof(y) == \"{name}\");",
+            name = name,
+            value = value
+        );
+        assert_eq!(run(&source), Ok("true true\n".into()), "{}", source);
+    }
+    assert_eq!(names.lines().count(), values.len());
+}